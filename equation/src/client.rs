@@ -1,8 +1,13 @@
 use crate::config::Config;
 use crate::proto::equation::{
-    adder_client::AdderClient, divider_client::DividerClient, multiplier_client::MultiplierClient,
-    subtractor_client::SubtractorClient,
+    adder_client::AdderClient, divider_client::DividerClient, functions_client::FunctionsClient,
+    modulo_client::ModuloClient, multiplier_client::MultiplierClient, power_client::PowerClient,
+    solver_client::SolverClient, subtractor_client::SubtractorClient,
 };
+use crate::server::Error;
+use async_trait::async_trait;
+use std::fmt::Debug;
+use tokio::sync::Mutex;
 use tonic::transport::Channel;
 
 const SCHEME: &str = "http://";
@@ -31,8 +36,150 @@ pub async fn build_divider_client(
     DividerClient::connect(build_url(&config.divider_addr)).await
 }
 
+pub async fn build_power_client(
+    config: &Config,
+) -> Result<PowerClient<Channel>, tonic::transport::Error> {
+    PowerClient::connect(build_url(&config.power_addr)).await
+}
+
+pub async fn build_modulo_client(
+    config: &Config,
+) -> Result<ModuloClient<Channel>, tonic::transport::Error> {
+    ModuloClient::connect(build_url(&config.modulo_addr)).await
+}
+
+pub async fn build_functions_client(
+    config: &Config,
+) -> Result<FunctionsClient<Channel>, tonic::transport::Error> {
+    FunctionsClient::connect(build_url(&config.functions_addr)).await
+}
+
+pub async fn build_solver_client(
+    config: &Config,
+) -> Result<SolverClient<Channel>, tonic::transport::Error> {
+    SolverClient::connect(build_url(&config.solver_addr)).await
+}
+
 fn build_url(conn_str: &str) -> String {
     let mut url = SCHEME.to_string();
     url.push_str(conn_str);
     url
 }
+
+/// Common connect signature across our generated clients so connections can be managed generically
+#[async_trait]
+pub trait Connect: Sized {
+    const NAME: &'static str;
+
+    async fn connect(config: &Config) -> Result<Self, tonic::transport::Error>;
+}
+
+#[async_trait]
+impl Connect for AdderClient<Channel> {
+    const NAME: &'static str = "adder client";
+
+    async fn connect(config: &Config) -> Result<Self, tonic::transport::Error> {
+        build_adder_client(config).await
+    }
+}
+
+#[async_trait]
+impl Connect for SubtractorClient<Channel> {
+    const NAME: &'static str = "subtractor client";
+
+    async fn connect(config: &Config) -> Result<Self, tonic::transport::Error> {
+        build_subtractor_client(config).await
+    }
+}
+
+#[async_trait]
+impl Connect for MultiplierClient<Channel> {
+    const NAME: &'static str = "multiplier client";
+
+    async fn connect(config: &Config) -> Result<Self, tonic::transport::Error> {
+        build_multiplier_client(config).await
+    }
+}
+
+#[async_trait]
+impl Connect for DividerClient<Channel> {
+    const NAME: &'static str = "divider client";
+
+    async fn connect(config: &Config) -> Result<Self, tonic::transport::Error> {
+        build_divider_client(config).await
+    }
+}
+
+#[async_trait]
+impl Connect for PowerClient<Channel> {
+    const NAME: &'static str = "power client";
+
+    async fn connect(config: &Config) -> Result<Self, tonic::transport::Error> {
+        build_power_client(config).await
+    }
+}
+
+#[async_trait]
+impl Connect for ModuloClient<Channel> {
+    const NAME: &'static str = "modulo client";
+
+    async fn connect(config: &Config) -> Result<Self, tonic::transport::Error> {
+        build_modulo_client(config).await
+    }
+}
+
+#[async_trait]
+impl Connect for FunctionsClient<Channel> {
+    const NAME: &'static str = "functions client";
+
+    async fn connect(config: &Config) -> Result<Self, tonic::transport::Error> {
+        build_functions_client(config).await
+    }
+}
+
+#[async_trait]
+impl Connect for SolverClient<Channel> {
+    const NAME: &'static str = "solver client";
+
+    async fn connect(config: &Config) -> Result<Self, tonic::transport::Error> {
+        build_solver_client(config).await
+    }
+}
+
+/// Client connection that may not be established yet
+/// All micro services start roughly the same time but have inter dependencies and require a
+/// persistant TCP connection so anything we can't reach on boot is retried at request time
+#[derive(Debug)]
+pub struct LazyClient<C> {
+    client: Mutex<Option<C>>,
+}
+
+impl<C: Connect + Clone + Debug> LazyClient<C> {
+    /// Try to connect now - failure is fine, we'll try again on first use
+    pub async fn new(config: &Config) -> Self {
+        Self {
+            client: Mutex::new(C::connect(config).await.ok()),
+        }
+    }
+
+    /// Client that will only ever connect on first use
+    pub fn empty() -> Self {
+        Self {
+            client: Mutex::new(None),
+        }
+    }
+
+    /// Get current service connection or try again
+    pub async fn get(&self, config: &Config) -> Result<C, Error> {
+        let mut client = self.client.lock().await;
+
+        if client.is_none() {
+            println!("No {} - retrying", C::NAME);
+            let res = C::connect(config).await;
+            println!("{} retry result {:?}", C::NAME, &res);
+            *client = res.ok();
+        }
+
+        client.clone().ok_or(Error::NoClientConnectionEstablished)
+    }
+}
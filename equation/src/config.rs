@@ -1,7 +1,17 @@
+use std::num::NonZeroUsize;
+
 const ADDER_ADDR: &str = "ADDER_ADDR";
 const SUBTRACTOR_ADDR: &str = "SUBTRACTOR_ADDR";
 const MULTIPLIER_ADDR: &str = "MULTIPLIER_ADDR";
 const DIVIDER_ADDR: &str = "DIVIDER_ADDR";
+const POWER_ADDR: &str = "POWER_ADDR";
+const MODULO_ADDR: &str = "MODULO_ADDR";
+const FUNCTIONS_ADDR: &str = "FUNCTIONS_ADDR";
+const SOLVER_ADDR: &str = "SOLVER_ADDR";
+const MAX_CONCURRENCY: &str = "MAX_CONCURRENCY";
+
+/// Remote calls a service will have in flight at once when MAX_CONCURRENCY isn't set
+const DEFAULT_MAX_CONCURRENCY: usize = 16;
 
 #[derive(Clone, Debug)]
 pub struct Config {
@@ -9,6 +19,11 @@ pub struct Config {
     pub subtractor_addr: String,
     pub multiplier_addr: String,
     pub divider_addr: String,
+    pub power_addr: String,
+    pub modulo_addr: String,
+    pub functions_addr: String,
+    pub solver_addr: String,
+    pub max_concurrency: usize,
 }
 
 impl Config {
@@ -20,6 +35,47 @@ impl Config {
             subtractor_addr: dotenv::var(SUBTRACTOR_ADDR).expect("ENVAR present"),
             multiplier_addr: dotenv::var(MULTIPLIER_ADDR).expect("ENVAR present"),
             divider_addr: dotenv::var(DIVIDER_ADDR).expect("ENVAR present"),
+            power_addr: dotenv::var(POWER_ADDR).expect("ENVAR present"),
+            modulo_addr: dotenv::var(MODULO_ADDR).expect("ENVAR present"),
+            functions_addr: dotenv::var(FUNCTIONS_ADDR).expect("ENVAR present"),
+            solver_addr: dotenv::var(SOLVER_ADDR).expect("ENVAR present"),
+            max_concurrency: max_concurrency(dotenv::var(MAX_CONCURRENCY).ok()),
         }
     }
 }
+
+/// Value of MAX_CONCURRENCY if it's set - zero is refused as a service with no permits would
+/// wait forever on its first remote call
+fn max_concurrency(value: Option<String>) -> usize {
+    value
+        .map(|v| {
+            v.parse::<NonZeroUsize>()
+                .expect("MAX_CONCURRENCY is a positive number")
+                .get()
+        })
+        .unwrap_or(DEFAULT_MAX_CONCURRENCY)
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_max_concurrency() {
+        assert_eq!(max_concurrency(None), DEFAULT_MAX_CONCURRENCY);
+        assert_eq!(max_concurrency(Some("1".to_string())), 1);
+        assert_eq!(max_concurrency(Some("64".to_string())), 64);
+    }
+
+    #[test]
+    #[should_panic(expected = "MAX_CONCURRENCY is a positive number")]
+    fn test_max_concurrency_zero() {
+        max_concurrency(Some("0".to_string()));
+    }
+}
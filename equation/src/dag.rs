@@ -0,0 +1,181 @@
+use std::{
+    collections::HashMap,
+    hash::{Hash, Hasher},
+};
+
+use crate::{number::Number, parse::MathAST, proto::equation::Operator};
+
+/// Position of a node in its Dag
+pub type NodeId = usize;
+
+/// A MathAST node with its operands replaced by the nodes they were merged into
+/// Two subexpressions are the same node exactly when their trees are structurally equal
+#[derive(Debug, Clone, PartialEq, Hash)]
+pub enum Node {
+    Value(Number),
+    Var(String),
+    Binary(Operator, NodeId, NodeId),
+    Negate(NodeId),
+    UnaryPlus(NodeId),
+    Call { name: String, args: Vec<NodeId> },
+}
+
+/// A node as a merge key - literals are the same when their representation is, floats compare
+/// their bits so a NaN literal merges with itself where `0.0` and `-0.0` stay apart
+struct Key(Node);
+
+impl PartialEq for Key {
+    fn eq(&self, other: &Self) -> bool {
+        match (&self.0, &other.0) {
+            (Node::Value(Number::F64(a)), Node::Value(Number::F64(b))) => {
+                a.to_bits() == b.to_bits()
+            }
+            (a, b) => a == b,
+        }
+    }
+}
+
+impl Eq for Key {}
+
+impl Hash for Key {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state)
+    }
+}
+
+/// A tree after common subexpression elimination - every distinct subexpression is a single
+/// node shared by all of its users, so `(a * b) + (a * b)` is four nodes rather than seven
+/// Nodes are stored after their operands
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dag {
+    nodes: Vec<Node>,
+    root: NodeId,
+}
+
+impl Dag {
+    pub fn new(ast: &MathAST) -> Self {
+        let mut nodes = Vec::new();
+        let root = insert(&mut nodes, &mut HashMap::new(), ast);
+        Self { nodes, root }
+    }
+
+    pub fn root(&self) -> NodeId {
+        self.root
+    }
+
+    pub fn node(&self, id: NodeId) -> &Node {
+        &self.nodes[id]
+    }
+
+    /// Every distinct subexpression, operands before the nodes using them
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    /// The tree rooted at node `id` with the sharing undone
+    pub fn to_ast(&self, id: NodeId) -> MathAST {
+        let operand = |id: &NodeId| Box::new(self.to_ast(*id));
+
+        match self.node(id) {
+            Node::Value(v) => MathAST::Value(v.clone()),
+            Node::Var(name) => MathAST::Var(name.clone()),
+            Node::Negate(v) => MathAST::Negate(operand(v)),
+            Node::UnaryPlus(v) => MathAST::UnaryPlus(operand(v)),
+            Node::Call { name, args } => MathAST::Call {
+                name: name.clone(),
+                args: args.iter().map(|arg| self.to_ast(*arg)).collect(),
+            },
+            Node::Binary(op, first, second) => {
+                let (first, second) = (operand(first), operand(second));
+                match op {
+                    Operator::Add => MathAST::Add(first, second),
+                    Operator::Subtract => MathAST::Subtract(first, second),
+                    Operator::Multiply => MathAST::Multiply(first, second),
+                    Operator::Divide => MathAST::Divide(first, second),
+                    Operator::Power => MathAST::Power(first, second),
+                    Operator::Remainder => MathAST::Remainder(first, second),
+                    Operator::FloorDivide => MathAST::FloorDivide(first, second),
+                    Operator::Unspecified => unreachable!("only concrete operators are merged"),
+                }
+            }
+        }
+    }
+}
+
+/// Add `ast` bottom up, reusing the node of any subexpression already seen
+/// Operands are merged first so a node is hashed along with its operands' ids, never their trees
+fn insert(nodes: &mut Vec<Node>, ids: &mut HashMap<Key, NodeId>, ast: &MathAST) -> NodeId {
+    let node = match ast {
+        MathAST::Value(v) => Node::Value(v.clone()),
+        MathAST::Var(name) => Node::Var(name.clone()),
+        MathAST::Negate(v) => Node::Negate(insert(nodes, ids, v)),
+        MathAST::UnaryPlus(v) => Node::UnaryPlus(insert(nodes, ids, v)),
+        MathAST::Call { name, args } => Node::Call {
+            name: name.clone(),
+            args: args.iter().map(|arg| insert(nodes, ids, arg)).collect(),
+        },
+        ast => {
+            let (op, first, second) = ast.as_binary().expect("every other node is binary");
+            Node::Binary(op, insert(nodes, ids, first), insert(nodes, ids, second))
+        }
+    };
+
+    *ids.entry(Key(node)).or_insert_with_key(|key| {
+        nodes.push(key.0.clone());
+        nodes.len() - 1
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::parse;
+    use std::collections::hash_map::DefaultHasher;
+
+    #[test]
+    fn test_dag() {
+        let ast = parse("(a * b) + (a * b) * max(a * b, 2)").unwrap();
+        let dag = Dag::new(&ast);
+
+        // a, b, a * b, 2, max(...), * and +
+        assert_eq!(dag.nodes().len(), 7);
+        assert_eq!(dag.to_ast(dag.root()), ast);
+        let product = match dag.node(dag.root()) {
+            Node::Binary(Operator::Add, first, _) => *first,
+            node => panic!("Expected a sum, got {:?}", node),
+        };
+        assert_eq!(dag.to_ast(product), parse("a * b").unwrap());
+
+        // Only identical trees merge - operands are ordered and literals keep their type
+        let dag = Dag::new(&parse("a * b - b * a").unwrap());
+        assert_eq!(dag.nodes().len(), 5);
+        let dag = Dag::new(&MathAST::Add(
+            Box::new(MathAST::Value(Number::I32(1))),
+            Box::new(MathAST::Value(Number::I64(1))),
+        ));
+        assert_eq!(dag.nodes().len(), 3);
+        // Floats by their bits - the zeros differ, NaN is itself
+        let float = |v: f64| Box::new(MathAST::Value(Number::F64(v)));
+        let dag = Dag::new(&MathAST::Add(float(0.0), float(-0.0)));
+        assert_eq!(dag.nodes().len(), 3);
+        let dag = Dag::new(&MathAST::Add(float(f64::NAN), float(f64::NAN)));
+        assert_eq!(dag.nodes().len(), 2);
+    }
+
+    fn hash(value: &impl Hash) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn test_structural_hash() {
+        let tree = |input: &str| hash(&parse(input).unwrap());
+        assert_eq!(tree("a * b"), tree("(a) * (b)"));
+        assert_ne!(tree("a * b"), tree("b * a"));
+
+        // Equal numbers hash alike, whichever zero
+        assert_eq!(Number::F64(0.0), Number::F64(-0.0));
+        assert_eq!(hash(&Number::F64(0.0)), hash(&Number::F64(-0.0)));
+    }
+}
@@ -0,0 +1,208 @@
+use crate::{
+    parse::{Context, MathAST, MAX_DEPTH},
+    proto::equation::Operator,
+    server::Error,
+    simplify::simplify,
+};
+
+/// Derivative of `ast` with respect to `var`, simplified
+/// Covers the sum, product, quotient and power rules along with the chain rule through `abs` and
+/// `sqrt` - exponents containing `var` need a logarithm so they're rejected, as are the
+/// piecewise operators and functions (`%`, `//`, `floor`, `min`...) wherever `var` appears in them
+/// Integer domains are simplified as rationals so `x / 2` differentiates to a half, with the
+/// constants that turn out whole narrowed back to the domain of `ctx`
+/// Derivatives nested deeper than anything a parser accepts are rejected too
+pub fn differentiate(ast: &MathAST, var: &str, ctx: &Context) -> Result<MathAST, Error> {
+    let derivative = narrow(simplify(derive(ast, var)?, &ctx.exact()), ctx);
+    if derivative.depth() > MAX_DEPTH {
+        return Err(Error::NotDifferentiable {
+            var: var.to_string(),
+            reason: format!(
+                "the derivative is nested more than {} levels deep",
+                MAX_DEPTH
+            ),
+        });
+    }
+
+    Ok(derivative)
+}
+
+fn derive(ast: &MathAST, var: &str) -> Result<MathAST, Error> {
+    if !ast.variables().contains(var) {
+        return Ok(value(0));
+    }
+    let not_differentiable = |reason: String| Error::NotDifferentiable {
+        var: var.to_string(),
+        reason,
+    };
+
+    Ok(match ast {
+        MathAST::Var(_) => value(1),
+        MathAST::Negate(u) => MathAST::Negate(Box::new(derive(u, var)?)),
+        MathAST::UnaryPlus(u) => derive(u, var)?,
+        MathAST::Add(u, v) => add(derive(u, var)?, derive(v, var)?),
+        MathAST::Subtract(u, v) => subtract(derive(u, var)?, derive(v, var)?),
+        // (uv)' = u'v + uv'
+        MathAST::Multiply(u, v) => add(
+            multiply(derive(u, var)?, *v.clone()),
+            multiply(*u.clone(), derive(v, var)?),
+        ),
+        // (u/v)' = (u'v - uv') / v^2
+        MathAST::Divide(u, v) => divide(
+            subtract(
+                multiply(derive(u, var)?, *v.clone()),
+                multiply(*u.clone(), derive(v, var)?),
+            ),
+            power(*v.clone(), value(2)),
+        ),
+        // (u^n)' = n u^(n - 1) u'
+        MathAST::Power(u, n) => {
+            if n.variables().contains(var) {
+                return Err(not_differentiable(format!(
+                    "{} appears in an exponent",
+                    var
+                )));
+            }
+            multiply(
+                multiply(
+                    *n.clone(),
+                    power(*u.clone(), subtract(*n.clone(), value(1))),
+                ),
+                derive(u, var)?,
+            )
+        }
+        MathAST::Remainder(..) => {
+            return Err(not_differentiable(format!(
+                "{} isn't differentiable",
+                Operator::Remainder.symbol()
+            )))
+        }
+        MathAST::FloorDivide(..) => {
+            return Err(not_differentiable(format!(
+                "{} isn't differentiable",
+                Operator::FloorDivide.symbol()
+            )))
+        }
+        MathAST::Call { name, args } => match (name.as_str(), args.as_slice()) {
+            // abs(u)' = u' u / abs(u)
+            ("abs", [u]) => multiply(derive(u, var)?, divide(u.clone(), ast.clone())),
+            // sqrt(u)' = u' / (2 sqrt(u))
+            ("sqrt", [u]) => divide(derive(u, var)?, multiply(value(2), ast.clone())),
+            _ => {
+                return Err(not_differentiable(format!(
+                    "{}() isn't differentiable",
+                    name
+                )))
+            }
+        },
+        MathAST::Value(_) => unreachable!("values never contain a variable"),
+    })
+}
+
+fn narrow(ast: MathAST, ctx: &Context) -> MathAST {
+    match ast {
+        MathAST::Value(n) => MathAST::Value(n.clone().coerce(ctx).unwrap_or(n)),
+        ast => ast.map_children(|operand| narrow(operand, ctx)),
+    }
+}
+
+fn value(n: i32) -> MathAST {
+    MathAST::Value(n.into())
+}
+
+fn add(u: MathAST, v: MathAST) -> MathAST {
+    MathAST::Add(Box::new(u), Box::new(v))
+}
+
+fn subtract(u: MathAST, v: MathAST) -> MathAST {
+    MathAST::Subtract(Box::new(u), Box::new(v))
+}
+
+fn multiply(u: MathAST, v: MathAST) -> MathAST {
+    MathAST::Multiply(Box::new(u), Box::new(v))
+}
+
+fn divide(u: MathAST, v: MathAST) -> MathAST {
+    MathAST::Divide(Box::new(u), Box::new(v))
+}
+
+fn power(u: MathAST, v: MathAST) -> MathAST {
+    MathAST::Power(Box::new(u), Box::new(v))
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+
+    use super::*;
+    use crate::{
+        number::Number,
+        parse::{parse, tests::TestASTEvaluator, Bindings, MathASTEvaluator},
+        proto::equation::NumericDomain,
+    };
+
+    fn derivative(input: &str) -> MathAST {
+        differentiate(&parse(input).unwrap(), "x", &Context::default()).unwrap()
+    }
+
+    /// Value of the derivative of `input` at x = `at` in exact arithmetic
+    async fn slope(input: &str, at: i32) -> Number {
+        let ctx = Context::new(NumericDomain::Rational);
+        let bindings: Bindings = [("x".to_string(), at.into())].into_iter().collect();
+        let ast = derivative(input).bind(&bindings).unwrap();
+        TestASTEvaluator::default().eval(&ctx, ast).await.unwrap()
+    }
+
+    fn rational(literal: &str) -> Number {
+        Number::from_literal(literal)
+            .coerce(&Context::new(NumericDomain::Rational))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_differentiate() {
+        assert_eq!(derivative("x ^ 2"), parse("2 * x").unwrap());
+        assert_eq!(derivative("3 * x + y - 7"), parse("3").unwrap());
+        assert_eq!(derivative("-(x) + x * y"), parse("-1 + y").unwrap());
+        assert_eq!(derivative("x ^ 3 - 4 * x"), parse("3 * x ^ 2 - 4").unwrap());
+        assert_eq!(derivative("x / 2"), MathAST::Value(rational("0.5")));
+    }
+
+    #[actix_rt::test]
+    async fn test_differentiate_rules() {
+        // Product: 2x(x + 1) + x^2
+        assert_eq!(slope("x ^ 2 * (x + 1)", 2).await, rational("16"));
+        // Quotient: ((x - 1) - (x + 1)) / (x - 1)^2
+        assert_eq!(slope("(x + 1) / (x - 1)", 3).await, rational("-0.5"));
+        // Chain: 2(3x + 1) * 3
+        assert_eq!(slope("(3 * x + 1) ^ 2", 1).await, rational("24"));
+        // Chain through built in functions: 2x / (2 sqrt(x^2 + 9))
+        assert_eq!(slope("sqrt(x ^ 2 + 9)", 4).await, rational("0.8"));
+        assert_eq!(slope("abs(1 - x)", 3).await, rational("1"));
+        assert_eq!(slope("abs(1 - x)", -3).await, rational("-1"));
+    }
+
+    #[test]
+    fn test_differentiate_invalid() {
+        let ctx = Context::default();
+        let invalid = |input: &str| match differentiate(&parse(input).unwrap(), "x", &ctx) {
+            Err(Error::NotDifferentiable { reason, .. }) => reason,
+            res => panic!("Expected no derivative for {}, got {:?}", input, res),
+        };
+
+        assert_eq!(invalid("2 ^ x"), "x appears in an exponent");
+        assert_eq!(invalid("x % 2"), "% isn't differentiable");
+        assert_eq!(invalid("max(x, 1)"), "max() isn't differentiable");
+        // Each factor of a product nests its derivative two levels deeper
+        assert_eq!(
+            invalid(&vec!["x"; 200].join(" * ")),
+            "the derivative is nested more than 256 levels deep"
+        );
+        assert!(differentiate(&parse(&vec!["x"; 100].join(" * ")).unwrap(), "x", &ctx).is_ok());
+        // Only when the variable is inside them
+        assert_matches!(
+            differentiate(&parse("floor(y) * x").unwrap(), "x", &ctx),
+            Ok(MathAST::Call { .. })
+        );
+    }
+}
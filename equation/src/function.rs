@@ -0,0 +1,316 @@
+use std::{cmp::Ordering, fmt};
+
+use num_bigint::BigInt;
+use num_traits::{Signed, Zero};
+
+use crate::{
+    number::{Integral, Number},
+    parse::Context,
+    proto::equation::Operator,
+    server::Error,
+};
+
+/// How many arguments a function takes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arity {
+    Exactly(usize),
+    AtLeast(usize),
+}
+
+impl Arity {
+    pub fn accepts(&self, count: usize) -> bool {
+        match self {
+            Arity::Exactly(n) => count == *n,
+            Arity::AtLeast(n) => count >= *n,
+        }
+    }
+}
+
+impl fmt::Display for Arity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (prefix, n) = match self {
+            Arity::Exactly(n) => ("", n),
+            Arity::AtLeast(n) => ("at least ", n),
+        };
+        let plural = if *n == 1 { "" } else { "s" };
+
+        write!(f, "{}{} argument{}", prefix, n, plural)
+    }
+}
+
+/// A built in function - arguments arrive already evaluated in the request's numeric domain
+pub struct Function {
+    pub name: &'static str,
+    pub arity: Arity,
+    apply: fn(&Context, Vec<Number>) -> Result<Number, Error>,
+}
+
+/// Every function the Functions service evaluates
+pub const FUNCTIONS: &[Function] = &[
+    Function {
+        name: "abs",
+        arity: Arity::Exactly(1),
+        apply: abs,
+    },
+    Function {
+        name: "min",
+        arity: Arity::AtLeast(1),
+        apply: |_, args| Ok(extreme(args, Ordering::Less)),
+    },
+    Function {
+        name: "max",
+        arity: Arity::AtLeast(1),
+        apply: |_, args| Ok(extreme(args, Ordering::Greater)),
+    },
+    Function {
+        name: "sqrt",
+        arity: Arity::Exactly(1),
+        apply: |ctx, args| only(args).sqrt(ctx),
+    },
+    Function {
+        name: "gcd",
+        arity: Arity::AtLeast(2),
+        apply: |ctx, args| fold_whole(ctx, "gcd", args, gcd),
+    },
+    Function {
+        name: "lcm",
+        arity: Arity::AtLeast(2),
+        apply: |ctx, args| fold_whole(ctx, "lcm", args, lcm),
+    },
+    Function {
+        name: "floor",
+        arity: Arity::Exactly(1),
+        apply: |ctx, args| only(args).to_integral(ctx, Integral::Floor),
+    },
+    Function {
+        name: "ceil",
+        arity: Arity::Exactly(1),
+        apply: |ctx, args| only(args).to_integral(ctx, Integral::Ceil),
+    },
+    Function {
+        name: "round",
+        arity: Arity::Exactly(1),
+        apply: |ctx, args| only(args).to_integral(ctx, Integral::Nearest),
+    },
+];
+
+pub fn lookup(name: &str) -> Option<&'static Function> {
+    FUNCTIONS.iter().find(|f| f.name == name)
+}
+
+/// Apply the function `name` to already evaluated arguments
+pub fn call(ctx: &Context, name: &str, args: Vec<Number>) -> Result<Number, Error> {
+    let invalid = |reason: String| Error::InvalidCall {
+        name: name.to_string(),
+        reason,
+    };
+
+    let function = lookup(name).ok_or_else(|| invalid("unknown function".to_string()))?;
+    if !function.arity.accepts(args.len()) {
+        return Err(invalid(format!(
+            "expects {}, got {}",
+            function.arity,
+            args.len()
+        )));
+    }
+
+    (function.apply)(ctx, args)
+}
+
+fn only(args: Vec<Number>) -> Number {
+    args.into_iter()
+        .next()
+        .expect("arity is checked before apply")
+}
+
+/// The most negative fixed width integer has no absolute value in its width - that's an
+/// overflow of `0 - value` like any other
+fn abs(ctx: &Context, args: Vec<Number>) -> Result<Number, Error> {
+    let value = only(args);
+    let overflow = |value: Number| Error::overflow(Operator::Subtract, 0.into(), value);
+
+    match value {
+        Number::I32(v) => v
+            .checked_abs()
+            .map(Number::I32)
+            .ok_or_else(|| overflow(value)),
+        Number::I64(v) => v
+            .checked_abs()
+            .map(Number::I64)
+            .ok_or_else(|| overflow(value)),
+        value if value.is_negative() => (-value).coerce(ctx),
+        value => Ok(value),
+    }
+}
+
+/// Smallest or largest argument - any NaN makes the result NaN
+fn extreme(args: Vec<Number>, keep: Ordering) -> Number {
+    args.into_iter()
+        .reduce(|best, value| match compare(&value, &best) {
+            Some(ordering) if ordering == keep => value,
+            Some(_) => best,
+            None => Number::F64(f64::NAN),
+        })
+        .expect("arity is checked before apply")
+}
+
+/// Arguments are always in the same domain so only floats can be unordered
+fn compare(a: &Number, b: &Number) -> Option<Ordering> {
+    match (a, b) {
+        (Number::F64(a), Number::F64(b)) => a.partial_cmp(b),
+        _ => Some(a.to_rational()?.cmp(&b.to_rational()?)),
+    }
+}
+
+/// Combine whole number arguments pairwise - fractions are rejected rather than truncated
+fn fold_whole(
+    ctx: &Context,
+    name: &str,
+    args: Vec<Number>,
+    combine: fn(BigInt, BigInt) -> BigInt,
+) -> Result<Number, Error> {
+    let whole = args
+        .into_iter()
+        .map(|value| match value.to_rational() {
+            Some(v) if v.is_integer() => Ok(v.to_integer()),
+            _ => Err(Error::InvalidCall {
+                name: name.to_string(),
+                reason: format!("expects whole numbers, got {}", value),
+            }),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let res = whole
+        .into_iter()
+        .reduce(combine)
+        .expect("arity is checked before apply");
+    Number::BigInt(res).coerce(ctx)
+}
+
+/// Always positive (or zero when both are)
+fn gcd(a: BigInt, b: BigInt) -> BigInt {
+    let (mut a, mut b) = (a.abs(), b.abs());
+    while !b.is_zero() {
+        let r = &a % &b;
+        a = b;
+        b = r;
+    }
+    a
+}
+
+fn lcm(a: BigInt, b: BigInt) -> BigInt {
+    if a.is_zero() || b.is_zero() {
+        return BigInt::zero();
+    }
+    let divisor = gcd(a.clone(), b.clone());
+    (a * b).abs() / divisor
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+
+    use super::*;
+    use crate::proto::equation::{NumericDomain, Outcome, Rounding};
+
+    fn numbers(values: &[i32]) -> Vec<Number> {
+        values.iter().map(|v| Number::from(*v)).collect()
+    }
+
+    #[test]
+    fn test_call() {
+        let ctx = Context::default();
+
+        assert_eq!(call(&ctx, "abs", numbers(&[-4])).unwrap(), 4.into());
+        assert_eq!(
+            call(&ctx, "min", numbers(&[3, -1, 2])).unwrap(),
+            (-1).into()
+        );
+        assert_eq!(call(&ctx, "max", numbers(&[3])).unwrap(), 3.into());
+        assert_eq!(call(&ctx, "max", numbers(&[3, 9, 2, 9])).unwrap(), 9.into());
+        assert_eq!(call(&ctx, "sqrt", numbers(&[49])).unwrap(), 7.into());
+        assert_eq!(
+            call(&ctx, "gcd", numbers(&[12, -18, 30])).unwrap(),
+            6.into()
+        );
+        assert_eq!(call(&ctx, "lcm", numbers(&[4, 6, 10])).unwrap(), 60.into());
+        assert_eq!(call(&ctx, "lcm", numbers(&[4, 0])).unwrap(), 0.into());
+        assert_eq!(call(&ctx, "floor", numbers(&[5])).unwrap(), 5.into());
+
+        assert_matches!(
+            call(&ctx, "abs", numbers(&[i32::MIN])),
+            Err(Error::Overflow {
+                op: Operator::Subtract,
+                ..
+            })
+        );
+        assert_matches!(
+            call(
+                &Context::new(NumericDomain::Int64),
+                "abs",
+                vec![Number::I64(i64::MIN)]
+            ),
+            Err(Error::Overflow { .. })
+        );
+        assert_matches!(
+            call(&ctx, "sqrt", numbers(&[2])),
+            Err(Error::OutOfDomain { .. })
+        );
+    }
+
+    #[test]
+    fn test_call_invalid() {
+        let ctx = Context::default();
+
+        assert_matches!(
+            call(&ctx, "nope", numbers(&[1])),
+            Err(Error::InvalidCall { reason, .. }) if reason == "unknown function"
+        );
+        assert_matches!(
+            call(&ctx, "sqrt", numbers(&[1, 2])),
+            Err(Error::InvalidCall { reason, .. }) if reason == "expects 1 argument, got 2"
+        );
+        assert_matches!(
+            call(&ctx, "min", vec![]),
+            Err(Error::InvalidCall { reason, .. }) if reason == "expects at least 1 argument, got 0"
+        );
+
+        let ctx = Context::new(NumericDomain::Rational);
+        let half = Number::from_literal("0.5").coerce(&ctx).unwrap();
+        assert_matches!(
+            call(&ctx, "gcd", vec![half, 2.into()]),
+            Err(Error::InvalidCall { reason, .. }) if reason == "expects whole numbers, got 1/2"
+        );
+    }
+
+    #[test]
+    fn test_call_domains() {
+        let ctx = Context::new(NumericDomain::Rational);
+        let value = |literal: &str| Number::from_literal(literal).coerce(&ctx).unwrap();
+
+        assert_eq!(
+            call(&ctx, "sqrt", vec![value("0.25")]).unwrap(),
+            value("0.5")
+        );
+        assert_eq!(
+            call(&ctx, "ceil", vec![value("-2.5")]).unwrap(),
+            value("-2")
+        );
+        assert_eq!(call(&ctx, "round", vec![value("2.5")]).unwrap(), value("2"));
+
+        let ctx = Context::decimal(4, Rounding::HalfUp);
+        let root = call(&ctx, "sqrt", numbers(&[2])).unwrap();
+        assert_eq!(root.to_string(), "1.4142");
+        assert_eq!(root.outcomes(), vec![Outcome::Rounded]);
+        let rounded = call(&ctx, "round", vec![Number::from_literal("2.5")]).unwrap();
+        assert_eq!(rounded.to_string(), "3.0000");
+
+        let ctx = Context::new(NumericDomain::Float64);
+        let nan = call(&ctx, "sqrt", vec![(-1.0).into()]).unwrap();
+        assert_eq!(nan.outcomes(), vec![Outcome::Nan]);
+        assert_eq!(
+            call(&ctx, "max", vec![1.0.into(), nan]).unwrap().outcomes(),
+            vec![Outcome::Nan]
+        );
+    }
+}
@@ -1,13 +1,19 @@
 pub mod client;
 pub mod config;
+pub mod dag;
+pub mod differentiate;
+pub mod function;
+pub mod number;
 pub mod parse;
 pub mod proto;
 pub mod server;
+pub mod service;
+pub mod simplify;
+pub mod solve;
+pub mod trace;
 
 #[cfg(test)]
 mod tests {
-    use super::*;
-
     #[test]
     fn it_works() {}
 }
@@ -0,0 +1,1099 @@
+use std::{
+    cmp::Ordering,
+    fmt,
+    hash::{Hash, Hasher},
+    ops::Neg,
+};
+
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{
+    CheckedAdd, CheckedDiv, CheckedMul, CheckedSub, FromPrimitive, Signed, ToPrimitive, Zero,
+};
+use tonic::Status;
+
+use crate::{
+    parse::Context,
+    proto::equation::{
+        number::Kind, Decimal as DecimalMessage, Number as NumberMessage, NumericDomain, Operator,
+        Outcome, Rational as RationalMessage, RemainderConvention, Rounding,
+    },
+    server::Error,
+};
+
+/// A value in one of our numeric domains
+/// Values carry their domain with them so a service can always tell what it's been handed
+#[derive(Debug, Clone, PartialEq)]
+pub enum Number {
+    I32(i32),
+    I64(i64),
+    BigInt(BigInt),
+    /// Always held in lowest terms
+    Rational(BigRational),
+    /// NaN and infinities are valid results here - see outcomes()
+    F64(f64),
+    Decimal(Decimal),
+}
+
+/// Fixed point value `mantissa * 10^-scale`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Decimal {
+    mantissa: BigInt,
+    scale: u32,
+    /// Set once any step producing this value had to round - it sticks through later operations
+    rounded: bool,
+}
+
+/// Which way to_integral() goes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Integral {
+    Floor,
+    Ceil,
+    Nearest,
+}
+
+impl Decimal {
+    pub fn new(mantissa: BigInt, scale: u32) -> Self {
+        Self {
+            mantissa,
+            scale,
+            rounded: false,
+        }
+    }
+
+    /// Nearest decimal with `scale` digits after the point, ties broken by `rounding`
+    pub fn round(value: &BigRational, scale: u32, rounding: Rounding) -> Self {
+        let scaled = value * BigRational::from_integer(BigInt::from(10).pow(scale));
+
+        Self {
+            mantissa: round_integer(&scaled, rounding),
+            scale,
+            rounded: !scaled.is_integer(),
+        }
+    }
+
+    pub fn scale(&self) -> u32 {
+        self.scale
+    }
+
+    pub fn is_rounded(&self) -> bool {
+        self.rounded
+    }
+
+    fn to_rational(&self) -> BigRational {
+        BigRational::new(self.mantissa.clone(), BigInt::from(10).pow(self.scale))
+    }
+}
+
+/// Round to a whole number - ties go to the even neighbour or away from zero
+fn round_integer(value: &BigRational, rounding: Rounding) -> BigInt {
+    let floor = value.floor();
+    let half = BigRational::new(1.into(), 2.into());
+    let tie = (value - &floor).cmp(&half);
+    let floor = floor.to_integer();
+
+    match tie {
+        Ordering::Less => floor,
+        Ordering::Greater => floor + 1,
+        Ordering::Equal => match rounding {
+            Rounding::HalfUp => value.round().to_integer(),
+            Rounding::HalfEven if (&floor % BigInt::from(2)).is_zero() => floor,
+            Rounding::HalfEven => floor + 1,
+        },
+    }
+}
+
+impl Number {
+    /// Value of a numeric literal
+    /// Whole numbers get the narrowest integer type that holds them, anything with a point is a Decimal
+    pub fn from_literal(literal: &str) -> Number {
+        if let Some((whole, fraction)) = literal.split_once('.') {
+            let mantissa = format!("{}{}", whole, fraction);
+            return Number::Decimal(Decimal::new(
+                mantissa.parse().expect("literal is only decimal digits"),
+                fraction.len() as u32,
+            ));
+        }
+
+        if let Ok(v) = literal.parse() {
+            Number::I32(v)
+        } else if let Ok(v) = literal.parse() {
+            Number::I64(v)
+        } else {
+            Number::BigInt(literal.parse().expect("literal is only decimal digits"))
+        }
+    }
+
+    pub fn domain(&self) -> NumericDomain {
+        match self {
+            Number::I32(_) => NumericDomain::Int32,
+            Number::I64(_) => NumericDomain::Int64,
+            Number::BigInt(_) => NumericDomain::BigInteger,
+            Number::Rational(_) => NumericDomain::Rational,
+            Number::F64(_) => NumericDomain::Float64,
+            Number::Decimal(_) => NumericDomain::Decimal,
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        match self {
+            Number::I32(v) => *v == 0,
+            Number::I64(v) => *v == 0,
+            Number::BigInt(v) => v.is_zero(),
+            Number::Rational(v) => v.is_zero(),
+            Number::F64(v) => *v == 0.0,
+            Number::Decimal(v) => v.mantissa.is_zero(),
+        }
+    }
+
+    /// Whether this is a decimal that has been rounded along the way
+    fn is_rounded(&self) -> bool {
+        matches!(self, Number::Decimal(v) if v.rounded)
+    }
+
+    /// Exact value - None for NaN and the infinities
+    pub(crate) fn to_rational(&self) -> Option<BigRational> {
+        match self {
+            Number::I32(v) => Some(BigRational::from_integer(BigInt::from(*v))),
+            Number::I64(v) => Some(BigRational::from_integer(BigInt::from(*v))),
+            Number::BigInt(v) => Some(BigRational::from_integer(v.clone())),
+            Number::Rational(v) => Some(v.clone()),
+            Number::F64(v) => BigRational::from_f64(*v),
+            Number::Decimal(v) => Some(v.to_rational()),
+        }
+    }
+
+    /// Convert into the domain of `ctx` - fails if the value can't be represented there
+    /// Fractions only convert to integer domains when they're whole numbers
+    /// Decimals are rounded to the context's scale
+    pub fn coerce(self, ctx: &Context) -> Result<Number, Error> {
+        let domain = ctx.domain;
+        match &self {
+            Number::Decimal(v) if domain == NumericDomain::Decimal && v.scale == ctx.scale => {
+                return Ok(self)
+            }
+            _ if domain != NumericDomain::Decimal && self.domain() == domain => return Ok(self),
+            _ => (),
+        }
+
+        let coerced = self.to_rational().and_then(|value| {
+            let integer = value.is_integer().then(|| value.to_integer());
+            match domain {
+                NumericDomain::Int32 => integer.and_then(|v| v.to_i32()).map(Number::I32),
+                NumericDomain::Int64 => integer.and_then(|v| v.to_i64()).map(Number::I64),
+                NumericDomain::BigInteger => integer.map(Number::BigInt),
+                NumericDomain::Rational => Some(Number::Rational(value)),
+                NumericDomain::Float64 => value.to_f64().map(Number::F64),
+                NumericDomain::Decimal => {
+                    let mut decimal = Decimal::round(&value, ctx.scale, ctx.rounding);
+                    decimal.rounded |= self.is_rounded();
+                    Some(Number::Decimal(decimal))
+                }
+            }
+        });
+
+        coerced.ok_or_else(|| Error::out_of_domain(self, domain))
+    }
+
+    /// Checked application of `op` in the domain of `ctx` - overflow and division by zero are errors, never panics
+    /// Floats follow IEEE 754 instead so dividing by zero gives an infinity or NaN
+    pub fn apply(op: Operator, ctx: &Context, lhs: Number, rhs: Number) -> Result<Number, Error> {
+        let (lhs, rhs) = (lhs.coerce(ctx)?, rhs.coerce(ctx)?);
+
+        if op == Operator::Power && ctx.domain != NumericDomain::Float64 {
+            return power(ctx, lhs, rhs);
+        }
+
+        let division = matches!(
+            op,
+            Operator::Divide | Operator::Remainder | Operator::FloorDivide
+        );
+        if division && rhs.is_zero() && ctx.domain != NumericDomain::Float64 {
+            return Err(Error::DivisionByZero);
+        }
+
+        if matches!(op, Operator::Remainder | Operator::FloorDivide) {
+            return whole_division(op, ctx, lhs, rhs);
+        }
+
+        let res = match (&lhs, &rhs) {
+            (Number::I32(a), Number::I32(b)) => checked(op, a, b).map(Number::I32),
+            (Number::I64(a), Number::I64(b)) => checked(op, a, b).map(Number::I64),
+            (Number::BigInt(a), Number::BigInt(b)) => checked(op, a, b).map(Number::BigInt),
+            (Number::Rational(a), Number::Rational(b)) => checked(op, a, b).map(Number::Rational),
+            (Number::F64(a), Number::F64(b)) => float(op, *a, *b).map(Number::F64),
+            (Number::Decimal(a), Number::Decimal(b)) => {
+                checked(op, &a.to_rational(), &b.to_rational()).map(|exact| {
+                    let mut decimal = Decimal::round(&exact, ctx.scale, ctx.rounding);
+                    decimal.rounded |= lhs.is_rounded() || rhs.is_rounded();
+                    Number::Decimal(decimal)
+                })
+            }
+            _ => unreachable!("both operands were coerced into the same domain"),
+        };
+
+        // Products and quotients of the unbounded domains grow as fast as powers do
+        res.filter(|res| res.bits() <= MAX_BITS)
+            .ok_or_else(|| Error::overflow(op, lhs, rhs))
+    }
+
+    /// Size of the larger of the numerator and denominator of the exact domains, 0 for the others
+    fn bits(&self) -> u64 {
+        match self {
+            Number::I32(_) | Number::I64(_) | Number::F64(_) => 0,
+            Number::BigInt(v) => v.bits(),
+            Number::Rational(v) => bits(v),
+            Number::Decimal(v) => v.mantissa.bits(),
+        }
+    }
+
+    /// False for NaN and zero (including -0.0)
+    pub fn is_negative(&self) -> bool {
+        match self {
+            Number::I32(v) => *v < 0,
+            Number::I64(v) => *v < 0,
+            Number::BigInt(v) => v.is_negative(),
+            Number::Rational(v) => v.is_negative(),
+            Number::F64(v) => *v < 0.0,
+            Number::Decimal(v) => v.mantissa.is_negative(),
+        }
+    }
+
+    /// Principal square root in the domain of `ctx`
+    /// Exact domains only take perfect squares, decimals are rounded to the context's scale
+    pub fn sqrt(self, ctx: &Context) -> Result<Number, Error> {
+        if let Number::F64(v) = self {
+            return Ok(Number::F64(v.sqrt()));
+        }
+
+        let value = self.to_rational().expect("only floats are non-finite");
+        let approx = value.to_f64().map_or(f64::NAN, f64::sqrt);
+        if value.is_negative() {
+            return Err(Error::out_of_domain(Number::F64(approx), ctx.domain));
+        }
+
+        let (numer, denom) = (value.numer().sqrt(), value.denom().sqrt());
+        if &numer * &numer == *value.numer() && &denom * &denom == *value.denom() {
+            let root = BigRational::new(numer, denom);
+            return match ctx.domain {
+                NumericDomain::Decimal => {
+                    let mut decimal = Decimal::round(&root, ctx.scale, ctx.rounding);
+                    decimal.rounded |= self.is_rounded();
+                    Ok(Number::Decimal(decimal))
+                }
+                _ => Number::Rational(root).coerce(ctx),
+            };
+        }
+
+        match ctx.domain {
+            // An irrational root is never a tie so one extra digit is enough to round correctly
+            NumericDomain::Decimal => {
+                let shift = BigInt::from(10).pow(2 * (ctx.scale + 1));
+                let digits = (value * BigRational::from_integer(shift))
+                    .floor()
+                    .to_integer()
+                    .sqrt();
+                Ok(Number::Decimal(Decimal {
+                    mantissa: (digits + 5) / 10,
+                    scale: ctx.scale,
+                    rounded: true,
+                }))
+            }
+            domain => Err(Error::out_of_domain(Number::F64(approx), domain)),
+        }
+    }
+
+    /// Whole number next to this value in the direction of `how`, kept in the domain of `ctx`
+    /// Ties to the nearest go the way the context's rounding says
+    pub fn to_integral(self, ctx: &Context, how: Integral) -> Result<Number, Error> {
+        if let Number::F64(v) = self {
+            return Ok(Number::F64(match (how, ctx.rounding) {
+                (Integral::Floor, _) => v.floor(),
+                (Integral::Ceil, _) => v.ceil(),
+                (Integral::Nearest, Rounding::HalfEven) => round_half_even(v),
+                (Integral::Nearest, Rounding::HalfUp) => v.round(),
+            }));
+        }
+
+        let value = self.to_rational().expect("only floats are non-finite");
+        let whole = match how {
+            Integral::Floor => value.floor().to_integer(),
+            Integral::Ceil => value.ceil().to_integer(),
+            Integral::Nearest => round_integer(&value, ctx.rounding),
+        };
+
+        match ctx.domain {
+            NumericDomain::Decimal => {
+                let mut decimal = Decimal::round(&whole.into(), ctx.scale, ctx.rounding);
+                decimal.rounded |= self.is_rounded();
+                Ok(Number::Decimal(decimal))
+            }
+            _ => Number::BigInt(whole).coerce(ctx),
+        }
+    }
+
+    /// Notable things about this value that aren't errors - NaN, infinities and rounded decimals
+    pub fn outcomes(&self) -> Vec<Outcome> {
+        match self {
+            Number::F64(v) if v.is_nan() => vec![Outcome::Nan],
+            Number::F64(v) if v.is_infinite() => vec![Outcome::Infinite],
+            Number::Decimal(v) if v.rounded => vec![Outcome::Rounded],
+            _ => vec![],
+        }
+    }
+
+    /// Render as a decimal rounded (half away from zero) to `places` digits after the point
+    pub fn to_decimal_string(&self, places: usize) -> String {
+        let value = match self.to_rational() {
+            Some(value) => value,
+            None => return self.to_string(),
+        };
+        let scale = BigInt::from(10).pow(places as u32);
+        let scaled = (value.abs() * BigRational::from_integer(scale.clone()))
+            .round()
+            .to_integer();
+
+        let sign = if value.is_negative() && !scaled.is_zero() {
+            "-"
+        } else {
+            ""
+        };
+        let whole = &scaled / &scale;
+
+        if places == 0 {
+            format!("{}{}", sign, whole)
+        } else {
+            let fraction = (&scaled % &scale).to_string();
+            format!("{}{}.{:0>places$}", sign, whole, fraction, places = places)
+        }
+    }
+}
+
+/// Arithmetic shared by every exact domain - integer division truncates toward zero
+/// None on overflow, which includes the one unrepresentable division MIN / -1
+fn checked<T>(op: Operator, a: &T, b: &T) -> Option<T>
+where
+    T: CheckedAdd + CheckedSub + CheckedMul + CheckedDiv,
+{
+    match op {
+        Operator::Add => a.checked_add(b),
+        Operator::Subtract => a.checked_sub(b),
+        Operator::Multiply => a.checked_mul(b),
+        Operator::Divide => a.checked_div(b),
+        _ => None,
+    }
+}
+
+/// IEEE 754 arithmetic - never fails for a concrete operator
+fn float(op: Operator, a: f64, b: f64) -> Option<f64> {
+    match op {
+        Operator::Add => Some(a + b),
+        Operator::Subtract => Some(a - b),
+        Operator::Multiply => Some(a * b),
+        Operator::Divide => Some(a / b),
+        Operator::Power => Some(a.powf(b)),
+        _ => None,
+    }
+}
+
+/// Largest numerator or denominator an operation will build, in bits - the unbounded domains would
+/// otherwise happily try to allocate whatever `2 ^ 999999999` needs
+const MAX_BITS: u64 = 1 << 20;
+
+fn bits(v: &BigRational) -> u64 {
+    v.numer().bits().max(v.denom().bits())
+}
+
+/// `base ^ exponent` in the exact and decimal domains
+/// Whole exponents are exact, fractional ones are only accepted by decimals since the
+/// result is rounded there anyway
+fn power(ctx: &Context, base: Number, exponent: Number) -> Result<Number, Error> {
+    let exact_base = base.to_rational().expect("only floats are non-finite");
+    let exact_exponent = exponent.to_rational().expect("only floats are non-finite");
+
+    if !exact_exponent.is_integer() {
+        if ctx.domain != NumericDomain::Decimal {
+            return Err(Error::UnsupportedExponent {
+                exponent: Box::new(exponent),
+                domain: ctx.domain,
+            });
+        }
+
+        let approx = exact_base
+            .to_f64()
+            .zip(exact_exponent.to_f64())
+            .map_or(f64::NAN, |(b, e)| b.powf(e));
+        let mut decimal = BigRational::from_f64(approx)
+            .map(|v| Decimal::round(&v, ctx.scale, ctx.rounding))
+            .ok_or_else(|| Error::out_of_domain(Number::F64(approx), ctx.domain))?;
+        decimal.rounded = true;
+        return Ok(Number::Decimal(decimal));
+    }
+
+    let overflow = || Error::overflow(Operator::Power, base.clone(), exponent.clone());
+    let exp = exact_exponent.to_integer().to_i32().ok_or_else(overflow)?;
+    if bits(&exact_base).saturating_sub(1) * exp.unsigned_abs() as u64 > MAX_BITS {
+        return Err(overflow());
+    }
+    if exact_base.is_zero() && exp < 0 {
+        return Err(Error::DivisionByZero);
+    }
+
+    from_exact(Operator::Power, ctx, base, exponent, exact_base.pow(exp))
+}
+
+/// Floor division and remainders - the quotient is rounded to a whole number (down for `//`,
+/// as the request's RemainderConvention says for `%`) and the remainder is what's left over
+fn whole_division(op: Operator, ctx: &Context, lhs: Number, rhs: Number) -> Result<Number, Error> {
+    if let (Number::F64(a), Number::F64(b)) = (&lhs, &rhs) {
+        let (a, b) = (*a, *b);
+        // % on floats truncates
+        let truncated = a % b;
+        let res = match (op, ctx.remainder) {
+            (Operator::FloorDivide, _) => (a / b).floor(),
+            (_, RemainderConvention::Truncated) => truncated,
+            (_, RemainderConvention::Floored)
+                if truncated != 0.0 && (truncated < 0.0) != (b < 0.0) =>
+            {
+                truncated + b
+            }
+            (_, RemainderConvention::Floored) => truncated,
+            (_, RemainderConvention::Euclidean) => a.rem_euclid(b),
+        };
+        return Ok(Number::F64(res));
+    }
+
+    let a = lhs.to_rational().expect("only floats are non-finite");
+    let b = rhs.to_rational().expect("only floats are non-finite");
+    let ratio = &a / &b;
+    let quotient = match (op, ctx.remainder) {
+        (Operator::Remainder, RemainderConvention::Truncated) => ratio.trunc(),
+        // Round away from the divisor's sign so the remainder is never negative
+        (Operator::Remainder, RemainderConvention::Euclidean) if b.is_negative() => ratio.ceil(),
+        _ => ratio.floor(),
+    };
+
+    let res = if op == Operator::FloorDivide {
+        quotient
+    } else {
+        &a - &b * quotient
+    };
+    from_exact(op, ctx, lhs, rhs, res)
+}
+
+/// Bring a result worked out on exact values back into the domain of `ctx`
+/// A whole result that doesn't fit is an overflow, a fraction just isn't an integer
+fn from_exact(
+    op: Operator,
+    ctx: &Context,
+    lhs: Number,
+    rhs: Number,
+    res: BigRational,
+) -> Result<Number, Error> {
+    match ctx.domain {
+        NumericDomain::Decimal => {
+            let mut decimal = Decimal::round(&res, ctx.scale, ctx.rounding);
+            decimal.rounded |= lhs.is_rounded() || rhs.is_rounded();
+            Ok(Number::Decimal(decimal))
+        }
+        _ if res.is_integer() => Number::Rational(res)
+            .coerce(ctx)
+            .map_err(|_| Error::overflow(op, lhs, rhs)),
+        _ => Number::Rational(res).coerce(ctx),
+    }
+}
+
+/// Exact negation - integers end up in the narrowest type that holds them, like literals, so
+/// `-2147483648` is still a 32 bit value and `-(-2147483648)` is left for coerce() to reject
+impl Neg for Number {
+    type Output = Number;
+
+    fn neg(self) -> Self::Output {
+        match self {
+            Number::I32(v) => narrowest(-BigInt::from(v)),
+            Number::I64(v) => narrowest(-BigInt::from(v)),
+            Number::BigInt(v) => narrowest(-v),
+            Number::Rational(v) => Number::Rational(-v),
+            Number::F64(v) => Number::F64(-v),
+            Number::Decimal(v) => Number::Decimal(Decimal {
+                mantissa: -v.mantissa,
+                ..v
+            }),
+        }
+    }
+}
+
+/// Consistent with equality - floats are hashed by their bits with both zeros hashed as one,
+/// since `0.0 == -0.0`
+impl Hash for Number {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Number::I32(v) => v.hash(state),
+            Number::I64(v) => v.hash(state),
+            Number::BigInt(v) => v.hash(state),
+            Number::Rational(v) => v.hash(state),
+            Number::F64(v) if *v == 0.0 => 0f64.to_bits().hash(state),
+            Number::F64(v) => v.to_bits().hash(state),
+            Number::Decimal(v) => v.hash(state),
+        }
+    }
+}
+
+/// Nearest whole float with exact halves going to the even neighbour
+fn round_half_even(v: f64) -> f64 {
+    let rounded = v.round();
+    if v.fract().abs() == 0.5 && rounded % 2.0 != 0.0 {
+        // round() took the half away from zero, the even neighbour is back towards it
+        rounded - v.signum()
+    } else {
+        rounded
+    }
+}
+
+fn narrowest(v: BigInt) -> Number {
+    if let Some(v) = v.to_i32() {
+        Number::I32(v)
+    } else if let Some(v) = v.to_i64() {
+        Number::I64(v)
+    } else {
+        Number::BigInt(v)
+    }
+}
+
+impl fmt::Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Number::I32(v) => write!(f, "{}", v),
+            Number::I64(v) => write!(f, "{}", v),
+            Number::BigInt(v) => write!(f, "{}", v),
+            Number::Rational(v) => write!(f, "{}", v),
+            Number::F64(v) => write!(f, "{}", v),
+            Number::Decimal(v) => write!(f, "{}", v),
+        }
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.mantissa.is_negative() { "-" } else { "" };
+        let digits = self.mantissa.abs().to_string();
+        let scale = self.scale as usize;
+
+        if scale == 0 {
+            return write!(f, "{}{}", sign, digits);
+        }
+
+        let digits = format!("{:0>width$}", digits, width = scale + 1);
+        let (whole, fraction) = digits.split_at(digits.len() - scale);
+        write!(f, "{}{}.{}", sign, whole, fraction)
+    }
+}
+
+impl From<i32> for Number {
+    fn from(value: i32) -> Self {
+        Number::I32(value)
+    }
+}
+
+impl From<i64> for Number {
+    fn from(value: i64) -> Self {
+        Number::I64(value)
+    }
+}
+
+impl From<BigInt> for Number {
+    fn from(value: BigInt) -> Self {
+        Number::BigInt(value)
+    }
+}
+
+impl From<BigRational> for Number {
+    fn from(value: BigRational) -> Self {
+        Number::Rational(value)
+    }
+}
+
+impl From<f64> for Number {
+    fn from(value: f64) -> Self {
+        Number::F64(value)
+    }
+}
+
+impl From<Decimal> for Number {
+    fn from(value: Decimal) -> Self {
+        Number::Decimal(value)
+    }
+}
+
+impl From<Number> for NumberMessage {
+    fn from(value: Number) -> Self {
+        let kind = match value {
+            Number::I32(v) => Kind::Int32(v),
+            Number::I64(v) => Kind::Int64(v),
+            Number::BigInt(v) => Kind::BigInteger(v.to_string()),
+            Number::Rational(v) => Kind::Rational(RationalMessage {
+                numerator: v.numer().to_string(),
+                denominator: v.denom().to_string(),
+            }),
+            Number::F64(v) => Kind::Float64(v),
+            Number::Decimal(v) => Kind::Decimal(DecimalMessage {
+                mantissa: v.mantissa.to_string(),
+                scale: v.scale,
+                rounded: v.rounded,
+            }),
+        };
+
+        NumberMessage { kind: Some(kind) }
+    }
+}
+
+impl TryFrom<NumberMessage> for Number {
+    type Error = Status;
+
+    fn try_from(value: NumberMessage) -> Result<Self, Self::Error> {
+        match value.kind {
+            Some(Kind::Int32(v)) => Ok(Number::I32(v)),
+            Some(Kind::Int64(v)) => Ok(Number::I64(v)),
+            Some(Kind::BigInteger(v)) => parse_bigint(&v).map(Number::BigInt),
+            Some(Kind::Rational(v)) => {
+                let denominator = parse_bigint(&v.denominator)?;
+                if denominator.is_zero() {
+                    return Err(Status::invalid_argument("Rational with zero denominator"));
+                }
+
+                // new() reduces so values from other clients are always in lowest terms
+                Ok(Number::Rational(BigRational::new(
+                    parse_bigint(&v.numerator)?,
+                    denominator,
+                )))
+            }
+            Some(Kind::Float64(v)) => Ok(Number::F64(v)),
+            Some(Kind::Decimal(v)) => Ok(Number::Decimal(Decimal {
+                mantissa: parse_bigint(&v.mantissa)?,
+                scale: v.scale,
+                rounded: v.rounded,
+            })),
+            None => Err(Status::invalid_argument("Number with no value")),
+        }
+    }
+}
+
+fn parse_bigint(value: &str) -> Result<BigInt, Status> {
+    value
+        .parse()
+        .map_err(|_| Status::invalid_argument(format!("Invalid big integer: {:?}", value)))
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+
+    use super::*;
+
+    #[test]
+    fn test_coerce() {
+        assert_eq!(
+            Number::I32(7)
+                .coerce(&Context::new(NumericDomain::Int64))
+                .unwrap(),
+            Number::I64(7)
+        );
+        assert_eq!(
+            Number::I64(-7)
+                .coerce(&Context::new(NumericDomain::BigInteger))
+                .unwrap(),
+            Number::BigInt(BigInt::from(-7))
+        );
+        assert_matches!(
+            Number::I64(i64::MAX).coerce(&Context::new(NumericDomain::Int32)),
+            Err(Error::OutOfDomain {
+                domain: NumericDomain::Int32,
+                ..
+            })
+        );
+    }
+
+    #[test]
+    fn test_apply_domains() {
+        let big = Number::I32(i32::MAX);
+
+        assert_matches!(
+            Number::apply(
+                Operator::Add,
+                &Context::new(NumericDomain::Int32),
+                big.clone(),
+                1.into()
+            ),
+            Err(Error::Overflow { .. })
+        );
+        assert_eq!(
+            Number::apply(
+                Operator::Add,
+                &Context::new(NumericDomain::Int64),
+                big.clone(),
+                1.into()
+            )
+            .unwrap(),
+            Number::I64(i32::MAX as i64 + 1)
+        );
+        assert_eq!(
+            Number::apply(
+                Operator::Multiply,
+                &Context::new(NumericDomain::BigInteger),
+                Number::I64(i64::MAX),
+                Number::I64(i64::MAX)
+            )
+            .unwrap()
+            .to_string(),
+            "85070591730234615847396907784232501249"
+        );
+        assert_matches!(
+            Number::apply(
+                Operator::Divide,
+                &Context::new(NumericDomain::BigInteger),
+                1.into(),
+                0.into()
+            ),
+            Err(Error::DivisionByZero)
+        );
+
+        // Squaring repeatedly runs into the same limit as powers
+        for domain in [
+            NumericDomain::BigInteger,
+            NumericDomain::Rational,
+            NumericDomain::Decimal,
+        ] {
+            let ctx = Context::new(domain);
+            let mut square = Number::apply(Operator::Power, &ctx, 2.into(), 65536.into()).unwrap();
+            for _ in 0..3 {
+                square = Number::apply(Operator::Multiply, &ctx, square.clone(), square).unwrap();
+            }
+            assert_matches!(
+                Number::apply(Operator::Multiply, &ctx, square.clone(), square),
+                Err(Error::Overflow {
+                    op: Operator::Multiply,
+                    ..
+                }),
+                "{:?}",
+                domain
+            );
+        }
+    }
+
+    fn rational(numerator: i32, denominator: i32) -> Number {
+        Number::Rational(BigRational::new(numerator.into(), denominator.into()))
+    }
+
+    #[test]
+    fn test_rational() {
+        let ctx = Context::new(NumericDomain::Rational);
+
+        let half = Number::apply(Operator::Divide, &ctx, 7.into(), 14.into()).unwrap();
+        assert_eq!(half, rational(1, 2));
+        assert_eq!(half.to_string(), "1/2");
+
+        let whole = Number::apply(Operator::Add, &ctx, half.clone(), half.clone()).unwrap();
+        assert_eq!(whole, rational(1, 1));
+        assert_eq!(whole.to_string(), "1");
+        assert_eq!(
+            whole.coerce(&Context::new(NumericDomain::Int32)).unwrap(),
+            Number::I32(1)
+        );
+
+        assert_matches!(
+            half.clone()
+                .coerce(&Context::new(NumericDomain::BigInteger)),
+            Err(Error::OutOfDomain { .. })
+        );
+        assert_matches!(
+            Number::apply(Operator::Divide, &ctx, half, rational(0, 3)),
+            Err(Error::DivisionByZero)
+        );
+    }
+
+    #[test]
+    fn test_decimal_string() {
+        assert_eq!(rational(7, 2).to_decimal_string(3), "3.500");
+        assert_eq!(rational(2, 3).to_decimal_string(4), "0.6667");
+        assert_eq!(rational(-1, 3).to_decimal_string(2), "-0.33");
+        assert_eq!(rational(-1, 300).to_decimal_string(2), "0.00");
+        assert_eq!(rational(5, 2).to_decimal_string(0), "3");
+        assert_eq!(Number::I32(-12).to_decimal_string(1), "-12.0");
+        assert_eq!(Number::F64(f64::NAN).to_decimal_string(2), "NaN");
+    }
+
+    fn decimal(literal: &str) -> Number {
+        Number::from_literal(literal)
+    }
+
+    #[test]
+    fn test_float() {
+        let ctx = Context::new(NumericDomain::Float64);
+
+        assert_eq!(
+            Number::apply(Operator::Divide, &ctx, 1.into(), 4.into()).unwrap(),
+            Number::F64(0.25)
+        );
+        assert_eq!(decimal("0.1").coerce(&ctx).unwrap(), Number::F64(0.1));
+
+        let infinite = Number::apply(Operator::Divide, &ctx, 1.into(), 0.into()).unwrap();
+        assert_eq!(infinite, Number::F64(f64::INFINITY));
+        assert_eq!(infinite.outcomes(), vec![Outcome::Infinite]);
+
+        let nan = Number::apply(Operator::Subtract, &ctx, infinite.clone(), infinite).unwrap();
+        assert_eq!(nan.outcomes(), vec![Outcome::Nan]);
+        assert_matches!(
+            nan.coerce(&Context::new(NumericDomain::Rational)),
+            Err(Error::OutOfDomain { .. })
+        );
+
+        assert_eq!(
+            Number::F64(2.5)
+                .coerce(&Context::new(NumericDomain::Rational))
+                .unwrap(),
+            rational(5, 2)
+        );
+    }
+
+    #[test]
+    fn test_float_rounding() {
+        let round = |v: f64, rounding: Rounding| {
+            let ctx = Context {
+                rounding,
+                ..Context::new(NumericDomain::Float64)
+            };
+            Number::F64(v).to_integral(&ctx, Integral::Nearest).unwrap()
+        };
+
+        for (v, half_even, half_up) in [
+            (0.5, 0.0, 1.0),
+            (1.5, 2.0, 2.0),
+            (2.5, 2.0, 3.0),
+            (-2.5, -2.0, -3.0),
+            (2.4, 2.0, 2.0),
+            (-2.6, -3.0, -3.0),
+        ] {
+            assert_eq!(
+                round(v, Rounding::HalfEven),
+                Number::F64(half_even),
+                "{}",
+                v
+            );
+            assert_eq!(round(v, Rounding::HalfUp), Number::F64(half_up), "{}", v);
+        }
+    }
+
+    #[test]
+    fn test_decimal() {
+        let ctx = Context::decimal(2, Rounding::HalfEven);
+
+        let sum = Number::apply(Operator::Add, &ctx, decimal("0.1"), decimal("0.2")).unwrap();
+        assert_eq!(sum.to_string(), "0.30");
+        assert!(sum.outcomes().is_empty());
+
+        let third = Number::apply(Operator::Divide, &ctx, 1.into(), 3.into()).unwrap();
+        assert_eq!(third.to_string(), "0.33");
+        assert_eq!(third.outcomes(), vec![Outcome::Rounded]);
+
+        // Once rounded always rounded, even if later steps are exact
+        let tripled = Number::apply(Operator::Multiply, &ctx, third, 3.into()).unwrap();
+        assert_eq!(tripled.to_string(), "0.99");
+        assert_eq!(tripled.outcomes(), vec![Outcome::Rounded]);
+
+        assert_eq!(decimal("-0.05").to_string(), "-0.05");
+        assert_matches!(
+            Number::apply(Operator::Divide, &ctx, 1.into(), decimal("0.00")),
+            Err(Error::DivisionByZero)
+        );
+        assert_matches!(
+            decimal("1.5").coerce(&Context::new(NumericDomain::Int32)),
+            Err(Error::OutOfDomain { .. })
+        );
+        assert_eq!(
+            decimal("2.0")
+                .coerce(&Context::new(NumericDomain::Int32))
+                .unwrap(),
+            Number::I32(2)
+        );
+    }
+
+    #[test]
+    fn test_decimal_rounding() {
+        let round = |literal: &str, rounding| {
+            let ctx = Context::decimal(1, rounding);
+            decimal(literal).coerce(&ctx).unwrap().to_string()
+        };
+
+        assert_eq!(round("0.25", Rounding::HalfEven), "0.2");
+        assert_eq!(round("0.35", Rounding::HalfEven), "0.4");
+        assert_eq!(round("-0.25", Rounding::HalfEven), "-0.2");
+        assert_eq!(round("0.26", Rounding::HalfEven), "0.3");
+        assert_eq!(round("0.25", Rounding::HalfUp), "0.3");
+        assert_eq!(round("-0.25", Rounding::HalfUp), "-0.3");
+        assert_eq!(round("-0.24", Rounding::HalfUp), "-0.2");
+    }
+
+    #[test]
+    fn test_power() {
+        let pow = |domain, base: Number, exponent: Number| {
+            Number::apply(Operator::Power, &Context::new(domain), base, exponent)
+        };
+
+        assert_eq!(
+            pow(NumericDomain::Int32, 2.into(), 10.into()).unwrap(),
+            Number::I32(1024)
+        );
+        assert_eq!(
+            pow(NumericDomain::Int32, (-3).into(), 3.into()).unwrap(),
+            Number::I32(-27)
+        );
+        assert_matches!(
+            pow(NumericDomain::Int32, 2.into(), 31.into()),
+            Err(Error::Overflow {
+                op: Operator::Power,
+                ..
+            })
+        );
+        assert_eq!(
+            pow(NumericDomain::BigInteger, 2.into(), 100.into())
+                .unwrap()
+                .to_string(),
+            "1267650600228229401496703205376"
+        );
+        assert_matches!(
+            pow(NumericDomain::BigInteger, 2.into(), 999999999.into()),
+            Err(Error::Overflow { .. })
+        );
+
+        // Negative exponents only stay in integer domains when the result is whole
+        assert_matches!(
+            pow(NumericDomain::Int64, 2.into(), (-3).into()),
+            Err(Error::OutOfDomain { .. })
+        );
+        assert_eq!(
+            pow(NumericDomain::Int64, 1.into(), (-3).into()).unwrap(),
+            Number::I64(1)
+        );
+        assert_eq!(
+            pow(NumericDomain::Rational, 2.into(), (-3).into()).unwrap(),
+            rational(1, 8)
+        );
+        assert_matches!(
+            pow(NumericDomain::Rational, 0.into(), (-1).into()),
+            Err(Error::DivisionByZero)
+        );
+        assert_matches!(
+            pow(NumericDomain::Rational, 4.into(), rational(1, 2)),
+            Err(Error::UnsupportedExponent { .. })
+        );
+
+        // Real exponents in the non-integer domains
+        assert_eq!(
+            pow(NumericDomain::Float64, 2.into(), decimal("0.5")).unwrap(),
+            Number::F64(2f64.powf(0.5))
+        );
+        let root = Number::apply(
+            Operator::Power,
+            &Context::decimal(3, Rounding::HalfEven),
+            2.into(),
+            decimal("0.5"),
+        )
+        .unwrap();
+        assert_eq!(root.to_string(), "1.414");
+        assert_eq!(root.outcomes(), vec![Outcome::Rounded]);
+    }
+
+    #[test]
+    fn test_remainder_conventions() {
+        let rem = |domain, remainder, a: i32, b: i32| {
+            let ctx = Context {
+                remainder,
+                ..Context::new(domain)
+            };
+            Number::apply(Operator::Remainder, &ctx, a.into(), b.into())
+                .unwrap()
+                .to_string()
+        };
+
+        for domain in [NumericDomain::Int32, NumericDomain::Float64] {
+            let truncated = |a, b| rem(domain, RemainderConvention::Truncated, a, b);
+            let floored = |a, b| rem(domain, RemainderConvention::Floored, a, b);
+            let euclidean = |a, b| rem(domain, RemainderConvention::Euclidean, a, b);
+
+            assert_eq!(truncated(7, 3), "1");
+            assert_eq!(truncated(-7, 3), "-1");
+            assert_eq!(truncated(7, -3), "1");
+            assert_eq!(floored(-7, 3), "2");
+            assert_eq!(floored(7, -3), "-2");
+            assert_eq!(euclidean(-7, 3), "2");
+            assert_eq!(euclidean(7, -3), "1");
+            assert_eq!(euclidean(-7, -3), "2");
+        }
+
+        let ctx = Context::new(NumericDomain::Rational);
+        assert_eq!(
+            Number::apply(Operator::Remainder, &ctx, rational(7, 2), 1.into()).unwrap(),
+            rational(1, 2)
+        );
+        assert_matches!(
+            Number::apply(Operator::Remainder, &ctx, 1.into(), 0.into()),
+            Err(Error::DivisionByZero)
+        );
+    }
+
+    #[test]
+    fn test_floor_divide() {
+        let ctx = Context::default();
+        let floor_divide = |a: Number, b: Number| Number::apply(Operator::FloorDivide, &ctx, a, b);
+
+        assert_eq!(floor_divide(7.into(), 2.into()).unwrap(), Number::I32(3));
+        assert_eq!(
+            floor_divide((-7).into(), 2.into()).unwrap(),
+            Number::I32(-4)
+        );
+        assert_matches!(floor_divide(7.into(), 0.into()), Err(Error::DivisionByZero));
+        assert_matches!(
+            floor_divide(i32::MIN.into(), (-1).into()),
+            Err(Error::Overflow {
+                op: Operator::FloorDivide,
+                ..
+            })
+        );
+
+        let ctx = Context::decimal(2, Rounding::HalfEven);
+        assert_eq!(
+            Number::apply(Operator::FloorDivide, &ctx, decimal("7.5"), 2.into())
+                .unwrap()
+                .to_string(),
+            "3.00"
+        );
+
+        let ctx = Context::new(NumericDomain::Float64);
+        assert_eq!(
+            Number::apply(Operator::FloorDivide, &ctx, (-7).into(), 2.into()).unwrap(),
+            Number::F64(-4.0)
+        );
+    }
+
+    #[test]
+    fn test_message_round_trip() {
+        let value = Number::BigInt("-123456789012345678901234567890".parse().unwrap());
+        let message: NumberMessage = value.clone().into();
+        assert_eq!(Number::try_from(message).unwrap(), value);
+
+        let message: NumberMessage = rational(-3, 4).into();
+        assert_eq!(Number::try_from(message).unwrap(), rational(-3, 4));
+
+        let unreduced = NumberMessage {
+            kind: Some(Kind::Rational(RationalMessage {
+                numerator: "6".to_string(),
+                denominator: "-8".to_string(),
+            })),
+        };
+        assert_eq!(Number::try_from(unreduced).unwrap(), rational(-3, 4));
+
+        let message: NumberMessage = decimal("-12.345").into();
+        assert_eq!(Number::try_from(message).unwrap(), decimal("-12.345"));
+    }
+}
@@ -1,144 +1,2103 @@
-use crate::proto::equation::CalculationResponse;
+use crate::{
+    dag::{Dag, Node, NodeId},
+    number::Number,
+    proto::equation::{
+        expr::Kind, Assignment, BinaryExpr, CalculationRequest, CalculationResponse, CallExpr,
+        CallRequest, Context as ContextMessage, DerivativeRequest, DerivativeResponse,
+        EquationExpr, Expr, Number as NumberMessage, NumericDomain, Operator, ParseErrorDetail,
+        RemainderConvention, Rounding, SystemRequest, SystemResponse, UnaryExpr, UnaryOperator,
+    },
+    server::Error,
+    solve::Solution,
+    trace::Step,
+};
 use async_trait::async_trait;
-use serde::{Deserialize, Serialize};
-use tonic::Status;
+use futures::future::try_join_all;
+use prost::Message;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt,
+    str::FromStr,
+    sync::Mutex,
+    time::Instant,
+};
+use tokio::{sync::OnceCell, try_join};
+use tonic::{Code, Status};
+
+mod infix;
+mod lexer;
+mod render;
+mod rpn;
+mod sexpr;
+mod typeset;
+
+pub(crate) use lexer::MAX_DEPTH;
+pub use render::{Parenthesized, Rpn, SExpr};
+pub use typeset::{Latex, MathML};
 
 /// AST for the math operations covered in this challege
 /// Inspired by the new defunct [math-ast](https://crates.io/crates/math-ast)
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Equality and hashing are structural, see dag::Dag for merging repeated subtrees
+#[derive(Debug, Clone, PartialEq, Hash)]
 pub enum MathAST {
-    Value(i32),
+    Value(Number),
     Add(Box<MathAST>, Box<MathAST>),
     Subtract(Box<MathAST>, Box<MathAST>),
     Multiply(Box<MathAST>, Box<MathAST>),
     Divide(Box<MathAST>, Box<MathAST>),
+    /// First raised to the power of second
+    Power(Box<MathAST>, Box<MathAST>),
+    /// Remainder of first / second under the request's RemainderConvention
+    Remainder(Box<MathAST>, Box<MathAST>),
+    /// Quotient of first / second rounded down
+    FloorDivide(Box<MathAST>, Box<MathAST>),
+    Negate(Box<MathAST>),
+    /// Prefix `+` - kept so the tree matches what was written
+    UnaryPlus(Box<MathAST>),
+    /// Built in function - see crate::function for what's available
+    Call {
+        name: String,
+        args: Vec<MathAST>,
+    },
+    /// Named variable - substituted from the request's bindings before evaluation
+    Var(String),
+}
+
+/// Two sides of an equation such as `2 * X + 3 = 11` - see crate::solve
+#[derive(Debug, Clone, PartialEq)]
+pub struct Equation {
+    pub lhs: MathAST,
+    pub rhs: MathAST,
+}
+
+impl Equation {
+    /// Names of every variable on either side
+    pub fn variables(&self) -> BTreeSet<&str> {
+        let mut names = self.lhs.variables();
+        names.extend(self.rhs.variables());
+        names
+    }
+
+    /// Substitute the variables that have a value in `bindings`, the rest are left as unknowns
+    pub fn substitute(self, bindings: &Bindings) -> Equation {
+        Equation {
+            lhs: self.lhs.substitute(bindings),
+            rhs: self.rhs.substitute(bindings),
+        }
+    }
+}
+
+/// Values for the variables of an equation, keyed by name
+pub type Bindings = BTreeMap<String, Number>;
+
+impl MathAST {
+    /// Operator and operands of a binary node - None for values, variables, signs and calls
+    pub fn as_binary(&self) -> Option<(Operator, &MathAST, &MathAST)> {
+        let (op, first, second) = match self {
+            MathAST::Add(f, s) => (Operator::Add, f, s),
+            MathAST::Subtract(f, s) => (Operator::Subtract, f, s),
+            MathAST::Multiply(f, s) => (Operator::Multiply, f, s),
+            MathAST::Divide(f, s) => (Operator::Divide, f, s),
+            MathAST::Power(f, s) => (Operator::Power, f, s),
+            MathAST::Remainder(f, s) => (Operator::Remainder, f, s),
+            MathAST::FloorDivide(f, s) => (Operator::FloorDivide, f, s),
+            _ => return None,
+        };
+        Some((op, first, second))
+    }
+
+    /// Names of every variable in the tree
+    pub fn variables(&self) -> BTreeSet<&str> {
+        let mut names = BTreeSet::new();
+        self.collect_variables(&mut names);
+        names
+    }
+
+    fn collect_variables<'a>(&'a self, names: &mut BTreeSet<&'a str>) {
+        match self {
+            MathAST::Value(_) => {}
+            MathAST::Var(name) => {
+                names.insert(name);
+            }
+            MathAST::Negate(v) | MathAST::UnaryPlus(v) => v.collect_variables(names),
+            MathAST::Call { args, .. } => args.iter().for_each(|arg| arg.collect_variables(names)),
+            MathAST::Add(f, s)
+            | MathAST::Subtract(f, s)
+            | MathAST::Multiply(f, s)
+            | MathAST::Divide(f, s)
+            | MathAST::Power(f, s)
+            | MathAST::Remainder(f, s)
+            | MathAST::FloorDivide(f, s) => {
+                f.collect_variables(names);
+                s.collect_variables(names);
+            }
+        }
+    }
+
+    /// Levels of nesting - 1 for a value or variable, one more for each operation, sign and call
+    pub fn depth(&self) -> usize {
+        let children = match self {
+            MathAST::Value(_) | MathAST::Var(_) => return 1,
+            MathAST::Negate(v) | MathAST::UnaryPlus(v) => v.depth(),
+            MathAST::Call { args, .. } => args.iter().map(MathAST::depth).max().unwrap_or(0),
+            MathAST::Add(f, s)
+            | MathAST::Subtract(f, s)
+            | MathAST::Multiply(f, s)
+            | MathAST::Divide(f, s)
+            | MathAST::Power(f, s)
+            | MathAST::Remainder(f, s)
+            | MathAST::FloorDivide(f, s) => f.depth().max(s.depth()),
+        };
+        children + 1
+    }
+
+    /// Replace every variable with its value from `bindings`
+    /// Fails listing every unbound name rather than stopping at the first
+    pub fn bind(self, bindings: &Bindings) -> Result<MathAST, Error> {
+        let mut bound = bind_all(vec![self], bindings)?;
+        Ok(bound.pop().expect("one tree in, one tree out"))
+    }
+
+    /// Replace the variables that have a value in `bindings`, leaving any others in place
+    pub fn substitute(self, bindings: &Bindings) -> MathAST {
+        match self {
+            MathAST::Var(name) => match bindings.get(&name) {
+                Some(value) => MathAST::Value(value.clone()),
+                None => MathAST::Var(name),
+            },
+            ast => ast.map_children(|operand| operand.substitute(bindings)),
+        }
+    }
+
+    /// The same node with `f` applied to each of its operands in order - leaves are unchanged
+    pub fn map_children(self, mut f: impl FnMut(MathAST) -> MathAST) -> MathAST {
+        let mut operand = |v: Box<MathAST>| Box::new(f(*v));
+
+        match self {
+            MathAST::Value(_) | MathAST::Var(_) => self,
+            MathAST::Negate(v) => MathAST::Negate(operand(v)),
+            MathAST::UnaryPlus(v) => MathAST::UnaryPlus(operand(v)),
+            MathAST::Call { name, args } => MathAST::Call {
+                name,
+                args: args.into_iter().map(f).collect(),
+            },
+            MathAST::Add(f, s) => MathAST::Add(operand(f), operand(s)),
+            MathAST::Subtract(f, s) => MathAST::Subtract(operand(f), operand(s)),
+            MathAST::Multiply(f, s) => MathAST::Multiply(operand(f), operand(s)),
+            MathAST::Divide(f, s) => MathAST::Divide(operand(f), operand(s)),
+            MathAST::Power(f, s) => MathAST::Power(operand(f), operand(s)),
+            MathAST::Remainder(f, s) => MathAST::Remainder(operand(f), operand(s)),
+            MathAST::FloorDivide(f, s) => MathAST::FloorDivide(operand(f), operand(s)),
+        }
+    }
+}
+
+/// Bind several trees at once so the error lists what's missing across all of them
+fn bind_all(asts: Vec<MathAST>, bindings: &Bindings) -> Result<Vec<MathAST>, Error> {
+    let missing: BTreeSet<&str> = asts
+        .iter()
+        .flat_map(MathAST::variables)
+        .filter(|name| !bindings.contains_key(*name))
+        .collect();
+    if !missing.is_empty() {
+        return Err(Error::UnboundVariables(
+            missing.into_iter().map(str::to_string).collect(),
+        ));
+    }
+
+    Ok(asts
+        .into_iter()
+        .map(|ast| ast.substitute(bindings))
+        .collect())
+}
+
+/// Error produced while turning an equation string into a MathAST
+/// `start` and `end` are byte offsets into the input of the offending token
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub start: usize,
+    pub end: usize,
+    pub expected: String,
+    pub found: String,
+}
+
+impl ParseError {
+    /// Render the offending line of input with a caret underline beneath the error span
+    pub fn render(&self, input: &str) -> String {
+        let line_start = input[..self.start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = input[self.start..]
+            .find('\n')
+            .map_or(input.len(), |i| self.start + i);
+        let line_number = input[..line_start].matches('\n').count() + 1;
+
+        // Offsets are in bytes but the caret has to line up with characters
+        let column = input[line_start..self.start].chars().count();
+        let width = input[self.start..self.end.min(line_end)]
+            .chars()
+            .count()
+            .max(1);
+        let gutter = " ".repeat(line_number.to_string().len());
+
+        format!(
+            "error: {}\n{} |\n{} | {}\n{} | {}{}",
+            self,
+            gutter,
+            line_number,
+            &input[line_start..line_end],
+            gutter,
+            " ".repeat(column),
+            "^".repeat(width)
+        )
+    }
+
+    /// Map to an invalid argument status with the rendered diagnostic as the message
+    /// and a `ParseErrorDetail` encoded into the status details for programmatic clients
+    pub fn to_status(&self, input: &str) -> Status {
+        let rendered = self.render(input);
+        let detail = ParseErrorDetail {
+            start: self.start as u32,
+            end: self.end as u32,
+            expected: self.expected.clone(),
+            found: self.found.clone(),
+            rendered: rendered.clone(),
+        };
+
+        Status::with_details(
+            Code::InvalidArgument,
+            rendered,
+            detail.encode_to_vec().into(),
+        )
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "expected {}, found {} at {}..{}",
+            self.expected, self.found, self.start, self.end
+        )
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Parse an infix equation string like `( ( (3 + 3)*2) /4) - 2` into a MathAST
+/// Supports + - * / % and // with the usual precedence and left associativity, right associative
+/// ^ binding tighter, prefix signs (`-2 ^ 2` is `-(2 ^ 2)`), integer and decimal literals, named
+/// variables, calls to the built in functions like `max(x, 2)` and parentheses
+/// Nesting is limited to 256 levels
+pub fn parse(input: &str) -> Result<MathAST, ParseError> {
+    infix::parse_infix(input)
+}
+
+/// Parse an equation with an expression either side of `=` like `2 * X + 3 = 11`
+pub fn parse_equation(input: &str) -> Result<Equation, ParseError> {
+    infix::parse_infix_equation(input)
+}
+
+/// Parse a system of equations like `{2 * x + y = 5, x - y = 1}`
+pub fn parse_system(input: &str) -> Result<Vec<Equation>, ParseError> {
+    infix::parse_infix_system(input)
+}
+
+/// Parse postfix input like `3 3 + 2 * 4 / 2 -` - the format written by MathAST::rpn
+pub fn parse_rpn(input: &str) -> Result<MathAST, ParseError> {
+    rpn::parse_rpn(input)
+}
+
+/// Parse prefix input like `(- (/ (* (+ 3 3) 2) 4) 2)` - the format written by MathAST::sexpr
+pub fn parse_sexpr(input: &str) -> Result<MathAST, ParseError> {
+    sexpr::parse_sexpr(input)
 }
 
-/// AST Placeholder for ( ( (3 + 3)*2) /4) – 2 = X until we write a parser
+impl FromStr for MathAST {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse(s)
+    }
+}
+
+/// AST for the exercise equation ( ( (3 + 3)*2) /4) – 2 = X
 pub fn test_value() -> MathAST {
     MathAST::Subtract(
         Box::new(MathAST::Divide(
             Box::new(MathAST::Multiply(
                 Box::new(MathAST::Add(
-                    Box::new(MathAST::Value(3)),
-                    Box::new(MathAST::Value(3)),
+                    Box::new(MathAST::Value(3.into())),
+                    Box::new(MathAST::Value(3.into())),
                 )),
-                Box::new(MathAST::Value(2)),
+                Box::new(MathAST::Value(2.into())),
             )),
-            Box::new(MathAST::Value(4)),
+            Box::new(MathAST::Value(4.into())),
         )),
-        Box::new(MathAST::Value(2)),
+        Box::new(MathAST::Value(2.into())),
     )
 }
 
+/// Most digits a decimal keeps after the point - its results are scaled by `10^scale`
+pub const MAX_SCALE: u32 = 1000;
+
+/// Evaluation settings for a whole request
+/// Sent along with every delegated calculation so each service evaluates the same way
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Context {
+    pub domain: NumericDomain,
+    /// Digits kept after the decimal point, at most MAX_SCALE - only used by the decimal domain
+    pub scale: u32,
+    /// How decimal results are rounded to `scale`
+    pub rounding: Rounding,
+    /// Sign convention for `%`
+    pub remainder: RemainderConvention,
+}
+
+impl Default for Context {
+    fn default() -> Self {
+        Self {
+            domain: NumericDomain::Int32,
+            scale: 0,
+            rounding: Rounding::HalfEven,
+            remainder: RemainderConvention::Truncated,
+        }
+    }
+}
+
+impl Context {
+    pub fn new(domain: NumericDomain) -> Self {
+        Self {
+            domain,
+            ..Default::default()
+        }
+    }
+
+    /// Integer domains widened to rationals so division is exact - used for algebra where
+    /// `x / 2` has to mean a half rather than truncate
+    pub fn exact(&self) -> Context {
+        match self.domain {
+            NumericDomain::Int32 | NumericDomain::Int64 | NumericDomain::BigInteger => Context {
+                domain: NumericDomain::Rational,
+                ..self.clone()
+            },
+            _ => self.clone(),
+        }
+    }
+
+    /// Decimal evaluation with `scale` digits after the point
+    pub fn decimal(scale: u32, rounding: Rounding) -> Self {
+        Self {
+            domain: NumericDomain::Decimal,
+            scale,
+            rounding,
+            ..Default::default()
+        }
+    }
+}
+
 /// Implement an evaluator depding on the role of each micro service
 /// For example your Adder service would evaluate whole values for first and second args
 /// when adding but pass nested evaluations onto other services
+/// Sibling subtrees are independent so eval() works on both sides of an operation concurrently
 #[async_trait]
-pub trait MathASTEvaluator<E: Send + Sync> {
-    async fn add(&self, first: i32, second: i32) -> Result<i32, E>;
-    async fn subtract(&self, first: i32, second: i32) -> Result<i32, E>;
-    async fn multiply(&self, first: i32, second: i32) -> Result<i32, E>;
-    async fn divide(&self, first: i32, second: i32) -> Result<i32, E>;
-
-    async fn eval(&self, ast: MathAST) -> Result<MathAST, E> {
-        match ast {
-            MathAST::Value(_) => Ok(ast),
-            MathAST::Add(f, s) => match [*f, *s] {
-                [MathAST::Value(first), MathAST::Value(second)] => {
-                    Ok(MathAST::Value(self.add(first, second).await?))
+pub trait MathASTEvaluator<E: From<Error> + Send + Sync> {
+    async fn add(&self, ctx: &Context, first: Number, second: Number) -> Result<Number, E>;
+    async fn subtract(&self, ctx: &Context, first: Number, second: Number) -> Result<Number, E>;
+    async fn multiply(&self, ctx: &Context, first: Number, second: Number) -> Result<Number, E>;
+    async fn divide(&self, ctx: &Context, first: Number, second: Number) -> Result<Number, E>;
+    async fn power(&self, ctx: &Context, first: Number, second: Number) -> Result<Number, E>;
+    async fn remainder(&self, ctx: &Context, first: Number, second: Number) -> Result<Number, E>;
+    async fn floor_divide(&self, ctx: &Context, first: Number, second: Number)
+        -> Result<Number, E>;
+    async fn call(&self, ctx: &Context, name: &str, args: Vec<Number>) -> Result<Number, E>;
+
+    /// Who signs are attributed to in a trace - they're applied wherever they're reached
+    fn name(&self) -> &str {
+        "Local"
+    }
+
+    /// Apply a binary operator through its method
+    async fn binary(
+        &self,
+        ctx: &Context,
+        op: Operator,
+        first: Number,
+        second: Number,
+    ) -> Result<Number, E> {
+        match op {
+            Operator::Add => self.add(ctx, first, second).await,
+            Operator::Subtract => self.subtract(ctx, first, second).await,
+            Operator::Multiply => self.multiply(ctx, first, second).await,
+            Operator::Divide => self.divide(ctx, first, second).await,
+            Operator::Power => self.power(ctx, first, second).await,
+            Operator::Remainder => self.remainder(ctx, first, second).await,
+            Operator::FloorDivide => self.floor_divide(ctx, first, second).await,
+            Operator::Unspecified => unreachable!("only concrete operators are evaluated"),
+        }
+    }
+
+    /// Evaluate like eval() while recording every reduction - no Step when `ast` is a literal
+    /// Binary operators are attributed to the service owning them and calls to Functions
+    /// A repeated subexpression is evaluated once and its step recorded under its first user
+    async fn trace(&self, ctx: &Context, ast: MathAST) -> Result<(Number, Option<Step>), E> {
+        let dag = Dag::new(&ast);
+        let values: Vec<OnceCell<Number>> = dag.nodes().iter().map(|_| OnceCell::new()).collect();
+        let steps: Vec<Mutex<Option<Step>>> =
+            dag.nodes().iter().map(|_| Mutex::default()).collect();
+        self.trace_node(ctx, &dag, &values, &steps, dag.root())
+            .await
+    }
+
+    /// Value of node `id` as eval_node() works it out, along with its step for whichever user
+    /// collects it first - every later user gets the value alone
+    async fn trace_node(
+        &self,
+        ctx: &Context,
+        dag: &Dag,
+        values: &[OnceCell<Number>],
+        steps: &[Mutex<Option<Step>>],
+        id: NodeId,
+    ) -> Result<(Number, Option<Step>), E> {
+        let operand = |id: NodeId| self.trace_node(ctx, dag, values, steps, id);
+
+        let value = values[id].get_or_try_init(|| async {
+            let node = dag.node(id);
+            let (op, service, operands) = match node {
+                Node::Value(v) => return Ok(v.clone().coerce(ctx)?),
+                Node::Var(name) => return Err(Error::UnboundVariables(vec![name.clone()]).into()),
+                Node::Negate(v) => ("-".to_string(), self.name(), vec![*v]),
+                Node::UnaryPlus(v) => ("+".to_string(), self.name(), vec![*v]),
+                Node::Call { name, args } => (name.clone(), "Functions", args.clone()),
+                Node::Binary(op, f, s) => (op.symbol().to_string(), op.service(), vec![*f, *s]),
+            };
+
+            let traced = try_join_all(operands.into_iter().map(operand)).await?;
+            let (operands, operand_steps): (Vec<Number>, Vec<Option<Step>>) =
+                traced.into_iter().unzip();
+
+            let start = Instant::now();
+            let result = match node {
+                Node::Negate(_) => (-operands[0].clone()).coerce(ctx)?,
+                Node::UnaryPlus(_) => operands[0].clone(),
+                Node::Call { name, .. } => self.call(ctx, name, operands.clone()).await?,
+                Node::Binary(op, ..) => {
+                    let (first, second) = (operands[0].clone(), operands[1].clone());
+                    self.binary(ctx, *op, first, second).await?
                 }
-                [first, second] => Ok(MathAST::Add(
-                    Box::new(self.eval(first).await?),
-                    Box::new(self.eval(second).await?),
-                )),
-            },
-            MathAST::Subtract(f, s) => match [*f, *s] {
-                [MathAST::Value(first), MathAST::Value(second)] => {
-                    Ok(MathAST::Value(self.subtract(first, second).await?))
+                Node::Value(_) | Node::Var(_) => unreachable!("leaves have no step"),
+            };
+
+            let step = Step {
+                expr: dag.to_ast(id),
+                op,
+                operands,
+                result: result.clone(),
+                service: service.to_string(),
+                elapsed: start.elapsed(),
+                steps: operand_steps.into_iter().flatten().collect(),
+            };
+            *steps[id].lock().expect("steps are only ever moved") = Some(step);
+            Ok::<_, E>(result)
+        });
+        let value = value.await?.clone();
+        let step = steps[id].lock().expect("steps are only ever moved").take();
+        Ok((value, step))
+    }
+
+    /// Reduce a tree to its value, evaluating each distinct subexpression once however many
+    /// times it appears - the tree is merged into a Dag first and every node's value shared
+    /// Literals are brought into the request's numeric domain as they're reached
+    async fn eval(&self, ctx: &Context, ast: MathAST) -> Result<Number, E> {
+        let dag = Dag::new(&ast);
+        let values: Vec<OnceCell<Number>> = dag.nodes().iter().map(|_| OnceCell::new()).collect();
+        self.eval_node(ctx, &dag, &values, dag.root()).await
+    }
+
+    /// Value of node `id` - the first of its users to get here evaluates it while the others
+    /// wait, operands are evaluated concurrently
+    async fn eval_node(
+        &self,
+        ctx: &Context,
+        dag: &Dag,
+        values: &[OnceCell<Number>],
+        id: NodeId,
+    ) -> Result<Number, E> {
+        let operand = |id: NodeId| self.eval_node(ctx, dag, values, id);
+
+        let value = values[id].get_or_try_init(|| async {
+            match dag.node(id) {
+                Node::Value(v) => Ok(v.clone().coerce(ctx)?),
+                // Bindings are substituted before evaluation so any variable left is unbound
+                Node::Var(name) => Err(Error::UnboundVariables(vec![name.clone()]).into()),
+                // Cheap enough to never be worth a remote call
+                Node::Negate(v) => Ok((-operand(*v).await?).coerce(ctx)?),
+                Node::UnaryPlus(v) => operand(*v).await,
+                Node::Call { name, args } => {
+                    let args = try_join_all(args.iter().map(|arg| operand(*arg))).await?;
+                    self.call(ctx, name, args).await
                 }
-                [first, second] => Ok(MathAST::Subtract(
-                    Box::new(self.eval(first).await?),
-                    Box::new(self.eval(second).await?),
-                )),
-            },
-            MathAST::Multiply(f, s) => match [*f, *s] {
-                [MathAST::Value(first), MathAST::Value(second)] => {
-                    Ok(MathAST::Value(self.multiply(first, second).await?))
+                Node::Binary(op, f, s) => {
+                    let (first, second) = try_join!(operand(*f), operand(*s))?;
+                    self.binary(ctx, *op, first, second).await
                 }
-                [first, second] => Ok(MathAST::Multiply(
-                    Box::new(self.eval(first).await?),
-                    Box::new(self.eval(second).await?),
-                )),
-            },
-            MathAST::Divide(f, s) => match [*f, *s] {
-                [MathAST::Value(first), MathAST::Value(second)] => {
-                    Ok(MathAST::Value(self.divide(first, second).await?))
+            }
+        });
+        Ok(value.await?.clone())
+    }
+}
+
+impl From<MathAST> for Expr {
+    fn from(value: MathAST) -> Self {
+        let (op, first, second) = match value {
+            MathAST::Value(v) => {
+                return Expr {
+                    kind: Some(Kind::Value(v.into())),
                 }
-                [first, second] => Ok(MathAST::Divide(
-                    Box::new(self.eval(first).await?),
-                    Box::new(self.eval(second).await?),
-                )),
-            },
+            }
+            MathAST::Var(name) => {
+                return Expr {
+                    kind: Some(Kind::Var(name)),
+                }
+            }
+            MathAST::Add(f, s) => (Operator::Add, f, s),
+            MathAST::Subtract(f, s) => (Operator::Subtract, f, s),
+            MathAST::Multiply(f, s) => (Operator::Multiply, f, s),
+            MathAST::Divide(f, s) => (Operator::Divide, f, s),
+            MathAST::Power(f, s) => (Operator::Power, f, s),
+            MathAST::Remainder(f, s) => (Operator::Remainder, f, s),
+            MathAST::FloorDivide(f, s) => (Operator::FloorDivide, f, s),
+            MathAST::Negate(v) => return unary(UnaryOperator::Negate, *v),
+            MathAST::UnaryPlus(v) => return unary(UnaryOperator::Plus, *v),
+            MathAST::Call { name, args } => {
+                return Expr {
+                    kind: Some(Kind::Call(CallExpr {
+                        name,
+                        args: args.into_iter().map(Expr::from).collect(),
+                    })),
+                }
+            }
+        };
+
+        Expr {
+            kind: Some(Kind::Binary(Box::new(BinaryExpr {
+                op: op.into(),
+                first: Some(Box::new((*first).into())),
+                second: Some(Box::new((*second).into())),
+            }))),
         }
     }
 }
 
-impl TryFrom<MathAST> for CalculationResponse {
+fn unary(op: UnaryOperator, operand: MathAST) -> Expr {
+    Expr {
+        kind: Some(Kind::Unary(Box::new(UnaryExpr {
+            op: op.into(),
+            operand: Some(Box::new(operand.into())),
+        }))),
+    }
+}
+
+impl TryFrom<Expr> for MathAST {
     type Error = Status;
 
-    fn try_from(value: MathAST) -> Result<Self, Self::Error> {
-        if let MathAST::Value(v) = value {
-            Ok(CalculationResponse { result: v })
-        } else {
-            Err(Status::invalid_argument("MathAST with No Value"))
+    fn try_from(value: Expr) -> Result<Self, Self::Error> {
+        let binary = match value.kind {
+            Some(Kind::Value(v)) => return Ok(MathAST::Value(v.try_into()?)),
+            Some(Kind::Unary(unary)) => return try_from_unary(*unary),
+            Some(Kind::Call(call)) => return try_from_call(call),
+            Some(Kind::Var(name)) => return Ok(MathAST::Var(name)),
+            Some(Kind::Binary(binary)) => binary,
+            None => return Err(Status::invalid_argument("Expr with no value or operation")),
+        };
+
+        let first = Box::new(try_from_operand(binary.first)?);
+        let second = Box::new(try_from_operand(binary.second)?);
+
+        match Operator::from_i32(binary.op) {
+            Some(Operator::Add) => Ok(MathAST::Add(first, second)),
+            Some(Operator::Subtract) => Ok(MathAST::Subtract(first, second)),
+            Some(Operator::Multiply) => Ok(MathAST::Multiply(first, second)),
+            Some(Operator::Divide) => Ok(MathAST::Divide(first, second)),
+            Some(Operator::Power) => Ok(MathAST::Power(first, second)),
+            Some(Operator::Remainder) => Ok(MathAST::Remainder(first, second)),
+            Some(Operator::FloorDivide) => Ok(MathAST::FloorDivide(first, second)),
+            Some(Operator::Unspecified) | None => Err(Status::invalid_argument(format!(
+                "Expr with unknown operator: {}",
+                binary.op
+            ))),
         }
     }
 }
 
+fn try_from_unary(unary: UnaryExpr) -> Result<MathAST, Status> {
+    let operand = Box::new(try_from_operand(unary.operand)?);
+
+    match UnaryOperator::from_i32(unary.op) {
+        Some(UnaryOperator::Negate) => Ok(MathAST::Negate(operand)),
+        Some(UnaryOperator::Plus) => Ok(MathAST::UnaryPlus(operand)),
+        Some(UnaryOperator::Unspecified) | None => Err(Status::invalid_argument(format!(
+            "Expr with unknown unary operator: {}",
+            unary.op
+        ))),
+    }
+}
+
+fn try_from_call(call: CallExpr) -> Result<MathAST, Status> {
+    let args = call
+        .args
+        .into_iter()
+        .map(MathAST::try_from)
+        .collect::<Result<_, _>>()?;
+
+    Ok(MathAST::Call {
+        name: call.name,
+        args,
+    })
+}
+
+/// Wire bindings into Numbers - values are validated like any other Number
+fn try_from_bindings(bindings: HashMap<String, NumberMessage>) -> Result<Bindings, Status> {
+    bindings
+        .into_iter()
+        .map(|(name, value)| Ok((name, value.try_into()?)))
+        .collect()
+}
+
+fn to_bindings(bindings: &Bindings) -> HashMap<String, NumberMessage> {
+    bindings
+        .iter()
+        .map(|(name, value)| (name.clone(), value.clone().into()))
+        .collect()
+}
+
+/// Operands are optional on the wire (proto3 message fields) but required in a MathAST
+fn try_from_operand(operand: Option<Box<Expr>>) -> Result<MathAST, Status> {
+    let operand = operand.ok_or_else(|| Status::invalid_argument("Expr with missing operand"))?;
+    (*operand).try_into()
+}
+
+impl From<Context> for ContextMessage {
+    fn from(value: Context) -> Self {
+        ContextMessage {
+            domain: value.domain.into(),
+            scale: value.scale,
+            rounding: value.rounding.into(),
+            remainder: value.remainder.into(),
+        }
+    }
+}
+
+impl TryFrom<ContextMessage> for Context {
+    type Error = Status;
+
+    fn try_from(value: ContextMessage) -> Result<Self, Self::Error> {
+        let domain = NumericDomain::from_i32(value.domain).ok_or_else(|| {
+            Status::invalid_argument(format!("Unknown numeric domain: {}", value.domain))
+        })?;
+        let rounding = Rounding::from_i32(value.rounding).ok_or_else(|| {
+            Status::invalid_argument(format!("Unknown rounding mode: {}", value.rounding))
+        })?;
+        let remainder = RemainderConvention::from_i32(value.remainder).ok_or_else(|| {
+            Status::invalid_argument(format!("Unknown remainder convention: {}", value.remainder))
+        })?;
+        if value.scale > MAX_SCALE {
+            return Err(Status::invalid_argument(format!(
+                "Scale {} is more than {} digits",
+                value.scale, MAX_SCALE
+            )));
+        }
+
+        Ok(Context {
+            domain,
+            scale: value.scale,
+            rounding,
+            remainder,
+        })
+    }
+}
+
+impl CalculationRequest {
+    pub fn new(first: MathAST, second: MathAST, ctx: &Context) -> Self {
+        Self {
+            first_arg: Some(first.into()),
+            second_arg: Some(second.into()),
+            context: Some(ctx.clone().into()),
+            bindings: HashMap::new(),
+            trace: false,
+        }
+    }
+
+    /// Send values for the variables in the arguments along with the request
+    pub fn with_bindings(mut self, bindings: &Bindings) -> Self {
+        self.bindings = to_bindings(bindings);
+        self
+    }
+
+    /// Ask for every step taken to be returned along with the result
+    pub fn with_trace(mut self, trace: bool) -> Self {
+        self.trace = trace;
+        self
+    }
+
+    /// Validate and convert both arguments back into MathAST along with the request's Context
+    /// A missing context is the default (32 bit integer) evaluation
+    /// Variables are substituted from the request's bindings
+    pub fn into_args(self) -> Result<(MathAST, MathAST, Context), Status> {
+        let first = self
+            .first_arg
+            .ok_or_else(|| Status::invalid_argument("Missing first argument"))?;
+        let second = self
+            .second_arg
+            .ok_or_else(|| Status::invalid_argument("Missing second argument"))?;
+        let ctx = match self.context {
+            Some(ctx) => ctx.try_into()?,
+            None => Context::default(),
+        };
+
+        let bindings = try_from_bindings(self.bindings)?;
+
+        let mut args = bind_all(vec![first.try_into()?, second.try_into()?], &bindings)?;
+        let second = args.pop().expect("two trees in, two trees out");
+        let first = args.pop().expect("two trees in, two trees out");
+        Ok((first, second, ctx))
+    }
+}
+
+impl CallRequest {
+    pub fn new(name: &str, args: Vec<MathAST>, ctx: &Context) -> Self {
+        Self {
+            call: Some(CallExpr {
+                name: name.to_string(),
+                args: args.into_iter().map(Expr::from).collect(),
+            }),
+            context: Some(ctx.clone().into()),
+            bindings: HashMap::new(),
+            trace: false,
+        }
+    }
+
+    /// Send values for the variables in the arguments along with the request
+    pub fn with_bindings(mut self, bindings: &Bindings) -> Self {
+        self.bindings = to_bindings(bindings);
+        self
+    }
+
+    /// Ask for every step taken to be returned along with the result
+    pub fn with_trace(mut self, trace: bool) -> Self {
+        self.trace = trace;
+        self
+    }
+
+    /// Validate and convert the call back into a MathAST along with the request's Context
+    /// Variables are substituted from the request's bindings
+    pub fn into_args(self) -> Result<(MathAST, Context), Status> {
+        let call = self
+            .call
+            .ok_or_else(|| Status::invalid_argument("Missing call"))?;
+        let ctx = match self.context {
+            Some(ctx) => ctx.try_into()?,
+            None => Context::default(),
+        };
+
+        let bindings = try_from_bindings(self.bindings)?;
+
+        Ok((try_from_call(call)?.bind(&bindings)?, ctx))
+    }
+}
+
+impl From<Equation> for EquationExpr {
+    fn from(value: Equation) -> Self {
+        EquationExpr {
+            lhs: Some(value.lhs.into()),
+            rhs: Some(value.rhs.into()),
+        }
+    }
+}
+
+impl TryFrom<EquationExpr> for Equation {
+    type Error = Status;
+
+    fn try_from(value: EquationExpr) -> Result<Self, Self::Error> {
+        let side = |side: Option<Expr>| {
+            side.ok_or_else(|| Status::invalid_argument("Equation with a missing side"))?
+                .try_into()
+        };
+
+        Ok(Equation {
+            lhs: side(value.lhs)?,
+            rhs: side(value.rhs)?,
+        })
+    }
+}
+
+impl SystemRequest {
+    pub fn new(equations: Vec<Equation>, ctx: &Context) -> Self {
+        Self {
+            equations: equations.into_iter().map(EquationExpr::from).collect(),
+            context: Some(ctx.clone().into()),
+            bindings: HashMap::new(),
+        }
+    }
+
+    /// Send values for some of the variables - only the rest are solved for
+    pub fn with_bindings(mut self, bindings: &Bindings) -> Self {
+        self.bindings = to_bindings(bindings);
+        self
+    }
+
+    /// Validate and convert the equations with the bindings substituted along with the Context
+    pub fn into_args(self) -> Result<(Vec<Equation>, Context), Status> {
+        if self.equations.is_empty() {
+            return Err(Status::invalid_argument("Missing equations"));
+        }
+        let ctx = match self.context {
+            Some(ctx) => ctx.try_into()?,
+            None => Context::default(),
+        };
+        let bindings = try_from_bindings(self.bindings)?;
+
+        let equations = self
+            .equations
+            .into_iter()
+            .map(|equation| Ok(Equation::try_from(equation)?.substitute(&bindings)))
+            .collect::<Result<_, Status>>()?;
+        Ok((equations, ctx))
+    }
+}
+
+impl SystemResponse {
+    pub fn new(solution: Vec<Solution>) -> Self {
+        Self {
+            solution: solution
+                .into_iter()
+                .map(|s| Assignment {
+                    name: s.unknown,
+                    value: Some(s.value.into()),
+                })
+                .collect(),
+        }
+    }
+
+    pub fn into_solution(self) -> Result<Vec<Solution>, Status> {
+        self.solution
+            .into_iter()
+            .map(|assignment| {
+                let value = assignment
+                    .value
+                    .ok_or_else(|| Status::invalid_argument("Missing value"))?;
+                Ok(Solution {
+                    unknown: assignment.name,
+                    value: value.try_into()?,
+                })
+            })
+            .collect()
+    }
+}
+
+impl DerivativeRequest {
+    pub fn new(ast: MathAST, var: &str, ctx: &Context) -> Self {
+        Self {
+            expr: Some(ast.into()),
+            var: var.to_string(),
+            context: Some(ctx.clone().into()),
+        }
+    }
+
+    /// Validate and convert the expression and variable along with the request's Context
+    pub fn into_args(self) -> Result<(MathAST, String, Context), Status> {
+        let expr = self
+            .expr
+            .ok_or_else(|| Status::invalid_argument("Missing expression"))?;
+        if self.var.is_empty() {
+            return Err(Status::invalid_argument("Missing variable"));
+        }
+        let ctx = match self.context {
+            Some(ctx) => ctx.try_into()?,
+            None => Context::default(),
+        };
+
+        Ok((expr.try_into()?, self.var, ctx))
+    }
+}
+
+impl DerivativeResponse {
+    pub fn new(derivative: MathAST) -> Self {
+        Self {
+            derivative: Some(derivative.into()),
+        }
+    }
+
+    pub fn into_derivative(self) -> Result<MathAST, Status> {
+        self.derivative
+            .ok_or_else(|| Status::invalid_argument("Missing derivative"))?
+            .try_into()
+    }
+}
+
+impl CalculationResponse {
+    /// Response carrying `result` along with anything notable about it (NaN, rounding...)
+    pub fn new(result: Number) -> Self {
+        Self {
+            outcomes: result.outcomes().into_iter().map(i32::from).collect(),
+            result: Some(result.into()),
+            trace: None,
+        }
+    }
+
+    /// Return the steps taken to reach the result - for requests made with_trace
+    pub fn with_trace(mut self, step: Option<Step>) -> Self {
+        self.trace = step.map(Step::into);
+        self
+    }
+
+    pub fn into_result(self) -> Result<Number, Status> {
+        self.result
+            .ok_or_else(|| Status::invalid_argument("Missing result"))?
+            .try_into()
+    }
+
+    /// The result along with the steps that reached it, if they were asked for
+    pub fn into_traced_result(mut self) -> Result<(Number, Option<Step>), Status> {
+        let step = self.trace.take().map(Step::try_from).transpose()?;
+        Ok((self.into_result()?, step))
+    }
+}
+
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use assert_matches::assert_matches;
 
     use super::*;
+    use crate::function;
+    use num_rational::BigRational;
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
 
+    /// Evaluates every operation locally - shared with the tests of other modules
     #[derive(Default)]
-    struct TestASTEvaluator {}
+    pub(crate) struct TestASTEvaluator {}
 
     #[async_trait]
-    impl MathASTEvaluator<()> for TestASTEvaluator {
-        async fn add(&self, first: i32, second: i32) -> Result<i32, ()> {
-            Ok(first + second)
+    impl MathASTEvaluator<Error> for TestASTEvaluator {
+        async fn add(&self, ctx: &Context, first: Number, second: Number) -> Result<Number, Error> {
+            Number::apply(Operator::Add, ctx, first, second)
+        }
+        async fn subtract(
+            &self,
+            ctx: &Context,
+            first: Number,
+            second: Number,
+        ) -> Result<Number, Error> {
+            Number::apply(Operator::Subtract, ctx, first, second)
+        }
+        async fn multiply(
+            &self,
+            ctx: &Context,
+            first: Number,
+            second: Number,
+        ) -> Result<Number, Error> {
+            Number::apply(Operator::Multiply, ctx, first, second)
         }
-        async fn subtract(&self, first: i32, second: i32) -> Result<i32, ()> {
-            Ok(first - second)
+        async fn divide(
+            &self,
+            ctx: &Context,
+            first: Number,
+            second: Number,
+        ) -> Result<Number, Error> {
+            Number::apply(Operator::Divide, ctx, first, second)
         }
-        async fn multiply(&self, first: i32, second: i32) -> Result<i32, ()> {
-            Ok(first * second)
+        async fn power(
+            &self,
+            ctx: &Context,
+            first: Number,
+            second: Number,
+        ) -> Result<Number, Error> {
+            Number::apply(Operator::Power, ctx, first, second)
         }
-        async fn divide(&self, first: i32, second: i32) -> Result<i32, ()> {
-            Ok(first / second)
+        async fn remainder(
+            &self,
+            ctx: &Context,
+            first: Number,
+            second: Number,
+        ) -> Result<Number, Error> {
+            Number::apply(Operator::Remainder, ctx, first, second)
+        }
+        async fn floor_divide(
+            &self,
+            ctx: &Context,
+            first: Number,
+            second: Number,
+        ) -> Result<Number, Error> {
+            Number::apply(Operator::FloorDivide, ctx, first, second)
+        }
+        async fn call(
+            &self,
+            ctx: &Context,
+            name: &str,
+            args: Vec<Number>,
+        ) -> Result<Number, Error> {
+            function::call(ctx, name, args)
         }
     }
 
     #[actix_rt::test]
     async fn test_ast_eval() {
-        let mut ast = test_value();
-        let mut evaluator = TestASTEvaluator::default();
+        let evaluator = TestASTEvaluator::default();
+        let ctx = Context::default();
 
-        let depth = 10;
+        assert_eq!(evaluator.eval(&ctx, test_value()).await.unwrap(), 1.into());
+        assert_eq!(
+            evaluator
+                .eval(&ctx, MathAST::Value(7.into()))
+                .await
+                .unwrap(),
+            7.into()
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_ast_trace() {
+        let evaluator = TestASTEvaluator::default();
+        let ctx = Context::default();
+        let summary = |step: &Step| (step.op.clone(), step.service.clone(), step.operands.clone());
+
+        let (result, step) = evaluator.trace(&ctx, test_value()).await.unwrap();
+        let step = step.unwrap();
+        assert_eq!(result, 1.into());
+        assert_eq!(step.expr, test_value());
+        assert_eq!(step.result, 1.into());
+        assert_eq!(
+            step.flatten().into_iter().map(summary).collect::<Vec<_>>(),
+            vec![
+                (
+                    "-".to_string(),
+                    "Subtractor".to_string(),
+                    vec![3.into(), 2.into()]
+                ),
+                (
+                    "/".to_string(),
+                    "Divider".to_string(),
+                    vec![12.into(), 4.into()]
+                ),
+                (
+                    "*".to_string(),
+                    "Multiplier".to_string(),
+                    vec![6.into(), 2.into()]
+                ),
+                (
+                    "+".to_string(),
+                    "Adder".to_string(),
+                    vec![3.into(), 3.into()]
+                ),
+            ]
+        );
+
+        // Signs belong to whoever reached them, every argument gets a branch of its own
+        let ast = parse("-max(1 + 1, 2 * 3)").unwrap();
+        let (result, step) = evaluator.trace(&ctx, ast).await.unwrap();
+        let step = step.unwrap();
+        assert_eq!(result, (-6).into());
+        assert_eq!(
+            summary(&step),
+            ("-".to_string(), "Local".to_string(), vec![6.into()])
+        );
+        assert_eq!(
+            summary(&step.steps[0]),
+            (
+                "max".to_string(),
+                "Functions".to_string(),
+                vec![2.into(), 6.into()]
+            )
+        );
+        assert_eq!(step.steps[0].steps.len(), 2);
+
+        // Literals are already reduced
+        let (result, step) = evaluator.trace(&ctx, parse("7").unwrap()).await.unwrap();
+        assert_eq!((result, step), (7.into(), None));
+    }
+
+    #[actix_rt::test]
+    async fn test_ast_eval_checked() {
+        let evaluator = TestASTEvaluator::default();
+        let ctx = Context::default();
+
+        let ast = parse("5 / (2 - 2)").unwrap();
+        assert_matches!(evaluator.eval(&ctx, ast).await, Err(Error::DivisionByZero));
+
+        let ast = parse("2147483647 + 1").unwrap();
+        assert_matches!(
+            evaluator.eval(&ctx, ast).await,
+            Err(Error::Overflow { op: Operator::Add, lhs, rhs })
+                if *lhs == Number::I32(i32::MAX) && *rhs == Number::I32(1)
+        );
+
+        assert_matches!(
+            Number::apply(
+                Operator::Divide,
+                &Context::default(),
+                i32::MIN.into(),
+                (-1).into()
+            ),
+            Err(Error::Overflow {
+                op: Operator::Divide,
+                ..
+            })
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_ast_eval_domains() {
+        let evaluator = TestASTEvaluator::default();
+        let ast = parse("2147483647 * 2147483647").unwrap();
+
+        let ctx = Context::new(NumericDomain::Int64);
+        assert_eq!(
+            evaluator.eval(&ctx, ast.clone()).await.unwrap(),
+            Number::I64(4611686014132420609)
+        );
+
+        let ast = MathAST::Multiply(Box::new(ast.clone()), Box::new(ast));
+        let ctx = Context::new(NumericDomain::Int64);
+        assert_matches!(
+            evaluator.eval(&ctx, ast.clone()).await,
+            Err(Error::Overflow { .. })
+        );
+
+        let ctx = Context::new(NumericDomain::BigInteger);
+        assert_eq!(
+            evaluator.eval(&ctx, ast).await.unwrap().to_string(),
+            "21267647892944572736998860269687930881"
+        );
+
+        let ast = parse("(19.99 * 3) / 7").unwrap();
+        let ctx = Context::decimal(2, Rounding::HalfEven);
+        let res = evaluator.eval(&ctx, ast.clone()).await.unwrap();
+        assert_eq!(res.to_string(), "8.57");
+        assert!(!res.outcomes().is_empty());
+
+        let ctx = Context::new(NumericDomain::Float64);
+        let res = evaluator.eval(&ctx, ast).await.unwrap();
+        assert_eq!(res, Number::F64(19.99 * 3.0 / 7.0));
+
+        // Literals that don't fit the requested domain are rejected rather than truncated
+        let ast = parse("9223372036854775808 - 1").unwrap();
+        let ctx = Context::new(NumericDomain::Int64);
+        assert_matches!(
+            evaluator.eval(&ctx, ast).await,
+            Err(Error::OutOfDomain { .. })
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_ast_eval_unary() {
+        let evaluator = TestASTEvaluator::default();
+        let ctx = Context::default();
+
+        let ast = parse("-(3 + 4) * -2 - -2 ^ 2").unwrap();
+        assert_eq!(evaluator.eval(&ctx, ast).await.unwrap(), 18.into());
+
+        // -(-2147483648) doesn't fit back into 32 bits
+        let ast = parse("-(-2147483648 + 0)").unwrap();
+        assert_matches!(
+            evaluator.eval(&ctx, ast).await,
+            Err(Error::OutOfDomain { .. })
+        );
+
+        let ast = parse("-(0.25 * 2)").unwrap();
+        let ctx = Context::decimal(2, Rounding::HalfEven);
+        assert_eq!(
+            evaluator.eval(&ctx, ast).await.unwrap().to_string(),
+            "-0.50"
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_ast_eval_call() {
+        let evaluator = TestASTEvaluator::default();
+        let ctx = Context::default();
+
+        let ast = parse("max(1, 2 * 3, -4) + abs(2 - 5)").unwrap();
+        assert_eq!(evaluator.eval(&ctx, ast).await.unwrap(), 9.into());
+
+        // Errors in the arguments surface before the call is made
+        let ast = parse("min(1, 1 / 0)").unwrap();
+        assert_matches!(evaluator.eval(&ctx, ast).await, Err(Error::DivisionByZero));
+
+        let ast = parse("gcd(12, 18) * lcm(4, 6)").unwrap();
+        assert_eq!(evaluator.eval(&ctx, ast).await.unwrap(), 72.into());
+    }
+
+    /// Evaluator that tracks how many additions are in flight at once and in total
+    #[derive(Default)]
+    struct ConcurrencyEvaluator {
+        in_flight: AtomicUsize,
+        max_in_flight: AtomicUsize,
+        adds: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl MathASTEvaluator<Error> for ConcurrencyEvaluator {
+        async fn add(&self, ctx: &Context, first: Number, second: Number) -> Result<Number, Error> {
+            self.adds.fetch_add(1, Ordering::SeqCst);
+            let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+
+            TestASTEvaluator::default().add(ctx, first, second).await
+        }
+        async fn subtract(
+            &self,
+            ctx: &Context,
+            first: Number,
+            second: Number,
+        ) -> Result<Number, Error> {
+            TestASTEvaluator::default()
+                .subtract(ctx, first, second)
+                .await
+        }
+        async fn multiply(
+            &self,
+            ctx: &Context,
+            first: Number,
+            second: Number,
+        ) -> Result<Number, Error> {
+            TestASTEvaluator::default()
+                .multiply(ctx, first, second)
+                .await
+        }
+        async fn divide(
+            &self,
+            ctx: &Context,
+            first: Number,
+            second: Number,
+        ) -> Result<Number, Error> {
+            TestASTEvaluator::default().divide(ctx, first, second).await
+        }
+        async fn power(
+            &self,
+            ctx: &Context,
+            first: Number,
+            second: Number,
+        ) -> Result<Number, Error> {
+            TestASTEvaluator::default().power(ctx, first, second).await
+        }
+        async fn remainder(
+            &self,
+            ctx: &Context,
+            first: Number,
+            second: Number,
+        ) -> Result<Number, Error> {
+            TestASTEvaluator::default()
+                .remainder(ctx, first, second)
+                .await
+        }
+        async fn floor_divide(
+            &self,
+            ctx: &Context,
+            first: Number,
+            second: Number,
+        ) -> Result<Number, Error> {
+            TestASTEvaluator::default()
+                .floor_divide(ctx, first, second)
+                .await
+        }
+        async fn call(
+            &self,
+            ctx: &Context,
+            name: &str,
+            args: Vec<Number>,
+        ) -> Result<Number, Error> {
+            TestASTEvaluator::default().call(ctx, name, args).await
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_ast_eval_concurrent() {
+        let evaluator = ConcurrencyEvaluator::default();
+
+        let ast = parse("((1 + 1) * (2 + 2)) - ((3 + 3) / (4 + 4))").unwrap();
+        let res = evaluator.eval(&Context::default(), ast).await.unwrap();
+
+        assert_eq!(evaluator.max_in_flight.load(Ordering::SeqCst), 4);
+        assert_eq!(res, 8.into());
+    }
+
+    #[actix_rt::test]
+    async fn test_ast_eval_shared() {
+        let evaluator = ConcurrencyEvaluator::default();
+
+        // Every user of `1 + 2` waits on the one addition
+        let ast = parse("(1 + 2) * (1 + 2) - max(1 + 2, 0)").unwrap();
+        let res = evaluator.eval(&Context::default(), ast).await.unwrap();
+
+        assert_eq!(evaluator.adds.load(Ordering::SeqCst), 1);
+        assert_eq!(res, 6.into());
+
+        // And traced once, under the first of them
+        let evaluator = ConcurrencyEvaluator::default();
+        let ast = parse("(1 + 2) * (1 + 2) - max(1 + 2, 0)").unwrap();
+        let (res, step) = evaluator.trace(&Context::default(), ast).await.unwrap();
+        let step = step.unwrap();
+        assert_eq!(evaluator.adds.load(Ordering::SeqCst), 1);
+        assert_eq!(res, 6.into());
+        let ops: Vec<&str> = step.flatten().iter().map(|step| step.op.as_str()).collect();
+        assert_eq!(ops, vec!["-", "*", "+", "max"]);
+        let product = &step.steps[0];
+        assert_eq!(product.operands, vec![3.into(), 3.into()]);
+        assert_eq!(product.steps.len(), 1);
+        assert!(step.steps[1].steps.is_empty());
+    }
+
+    fn value(v: i32) -> Box<MathAST> {
+        Box::new(MathAST::Value(v.into()))
+    }
+
+    #[test]
+    fn test_parse_exercise() {
+        assert_eq!(parse("( ( (3 + 3)*2) /4) - 2"), Ok(test_value()));
+        assert_eq!(parse("( ( (3 + 3)*2) /4) – 2"), Ok(test_value()));
+    }
+
+    #[test]
+    fn test_parse_precedence() {
+        assert_eq!(
+            parse("1 + 2 * 3"),
+            Ok(MathAST::Add(
+                value(1),
+                Box::new(MathAST::Multiply(value(2), value(3)))
+            ))
+        );
+        assert_eq!(
+            parse("(1 + 2) * 3"),
+            Ok(MathAST::Multiply(
+                Box::new(MathAST::Add(value(1), value(2))),
+                value(3)
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_left_associative() {
+        assert_eq!(
+            "8 - 4 - 2".parse(),
+            Ok(MathAST::Subtract(
+                Box::new(MathAST::Subtract(value(8), value(4))),
+                value(2)
+            ))
+        );
+        assert_eq!(
+            "8/4/2".parse(),
+            Ok(MathAST::Divide(
+                Box::new(MathAST::Divide(value(8), value(4))),
+                value(2)
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_power() {
+        assert_eq!(
+            parse("2 ^ 3 ^ 2"),
+            Ok(MathAST::Power(
+                value(2),
+                Box::new(MathAST::Power(value(3), value(2)))
+            ))
+        );
+        assert_eq!(
+            parse("2 * 3 ^ 2"),
+            Ok(MathAST::Multiply(
+                value(2),
+                Box::new(MathAST::Power(value(3), value(2)))
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_remainder() {
+        assert_eq!(
+            parse("7 % 3 // 2 * 4"),
+            Ok(MathAST::Multiply(
+                Box::new(MathAST::FloorDivide(
+                    Box::new(MathAST::Remainder(value(7), value(3))),
+                    value(2)
+                )),
+                value(4)
+            ))
+        );
+        assert_eq!(
+            parse("1 + 7 // 2"),
+            Ok(MathAST::Add(
+                value(1),
+                Box::new(MathAST::FloorDivide(value(7), value(2)))
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_unary() {
+        // Signs bind looser than ^ but tighter than everything else
+        assert_eq!(
+            parse("-2 ^ 2"),
+            Ok(MathAST::Negate(Box::new(MathAST::Power(
+                value(2),
+                value(2)
+            ))))
+        );
+        assert_eq!(parse("2 ^ -1"), Ok(MathAST::Power(value(2), value(-1))));
+        assert_eq!(parse("-2 * 3"), Ok(MathAST::Multiply(value(-2), value(3))));
+        assert_eq!(
+            parse("1 - -(3 + 4)"),
+            Ok(MathAST::Subtract(
+                value(1),
+                Box::new(MathAST::Negate(Box::new(MathAST::Add(value(3), value(4)))))
+            ))
+        );
+        assert_eq!(
+            parse("+(1 * 2) + +2"),
+            Ok(MathAST::Add(
+                Box::new(MathAST::UnaryPlus(Box::new(MathAST::Multiply(
+                    value(1),
+                    value(2)
+                )))),
+                value(2)
+            ))
+        );
+        assert_eq!(parse("--3"), Ok(MathAST::Value(3.into())));
+        assert_eq!(parse("-2147483648"), Ok(MathAST::Value(i32::MIN.into())));
+        assert_eq!(error_span("1 + -"), (5, 5, "end of input".to_string()));
+    }
+
+    #[test]
+    fn test_parse_call() {
+        assert_eq!(
+            parse("max(1, 2 * 3, -4)"),
+            Ok(MathAST::Call {
+                name: "max".to_string(),
+                args: vec![
+                    MathAST::Value(1.into()),
+                    MathAST::Multiply(value(2), value(3)),
+                    MathAST::Value((-4).into()),
+                ],
+            })
+        );
+        assert_eq!(
+            parse("-sqrt(4) ^ 2"),
+            Ok(MathAST::Negate(Box::new(MathAST::Power(
+                Box::new(MathAST::Call {
+                    name: "sqrt".to_string(),
+                    args: vec![MathAST::Value(4.into())],
+                }),
+                value(2)
+            ))))
+        );
+
+        assert_eq!(error_span("1 + nope(2)"), (4, 8, "'nope'".to_string()));
+        assert_eq!(error_span("sqrt 4"), (5, 6, "number 4".to_string()));
+        assert_eq!(error_span("abs(1 2)"), (6, 7, "number 2".to_string()));
+
+        // Arity mismatches span the whole call
+        let err = parse("1 + sqrt(1, 2)").unwrap_err();
+        assert_eq!((err.start, err.end), (4, 14));
+        assert_eq!(err.expected, "1 argument to sqrt");
+        assert_eq!(err.found, "2 arguments");
+    }
+
+    #[test]
+    fn test_parse_variables() {
+        let var = |name: &str| Box::new(MathAST::Var(name.to_string()));
+
+        assert_eq!(
+            parse("2 * x + rate_2"),
+            Ok(MathAST::Add(
+                Box::new(MathAST::Multiply(value(2), var("x"))),
+                var("rate_2")
+            ))
+        );
+        // Function names are only calls when followed by `(`
+        assert_eq!(
+            parse("-max + max(x)"),
+            Ok(MathAST::Add(
+                Box::new(MathAST::Negate(var("max"))),
+                Box::new(MathAST::Call {
+                    name: "max".to_string(),
+                    args: vec![MathAST::Var("x".to_string())],
+                })
+            ))
+        );
+        assert_eq!(error_span("2x"), (1, 2, "'x'".to_string()));
+    }
 
-        for _ in 0..depth {
-            ast = evaluator.eval(ast.clone()).await.unwrap();
+    #[test]
+    fn test_parse_equation() {
+        assert_eq!(
+            parse_equation("2 * X + 3 = 11"),
+            Ok(Equation {
+                lhs: MathAST::Add(
+                    Box::new(MathAST::Multiply(
+                        value(2),
+                        Box::new(MathAST::Var("X".to_string()))
+                    )),
+                    value(3)
+                ),
+                rhs: MathAST::Value(11.into()),
+            })
+        );
+
+        let err = parse_equation("2 * X 11").unwrap_err();
+        assert_eq!((err.start, err.end), (6, 8));
+        assert_eq!(err.expected, "an operator or '='");
+        assert_eq!(
+            parse_equation("X = 1 = 2").unwrap_err().found,
+            "'='".to_string()
+        );
+        // Plain expressions don't take an `=`
+        assert_eq!(error_span("X = 1"), (2, 3, "'='".to_string()));
+    }
+
+    #[test]
+    fn test_parse_system() {
+        let system = parse_system("{x + y = 5, x = max(y, 1)}").unwrap();
+        assert_eq!(system.len(), 2);
+        assert_eq!(system[1].lhs, MathAST::Var("x".to_string()));
+
+        let err = parse_system("x = 1").unwrap_err();
+        assert_eq!((err.start, err.end, err.expected.as_str()), (0, 1, "'{'"));
+        let err = parse_system("{x = 1 y = 2}").unwrap_err();
+        assert_eq!((err.start, err.end), (7, 8));
+        assert_eq!(err.expected, "an operator, ',' or '}'");
+        assert_eq!(error_span("{1} + 2"), (0, 1, "'{'".to_string()));
+    }
+
+    #[test]
+    fn test_bind() {
+        let ast = parse("x * (y + x) - abs(z)").unwrap();
+        assert_eq!(
+            ast.variables().into_iter().collect::<Vec<_>>(),
+            ["x", "y", "z"]
+        );
+
+        let bindings: Bindings = [("x", 2), ("y", 3), ("z", -4)]
+            .into_iter()
+            .map(|(name, v)| (name.to_string(), v.into()))
+            .collect();
+        assert_eq!(
+            ast.clone().bind(&bindings).unwrap(),
+            parse("2 * (3 + 2) - abs(-4)").unwrap()
+        );
+
+        let partial: Bindings = [("y".to_string(), 1.into())].into_iter().collect();
+        assert_matches!(
+            ast.bind(&partial),
+            Err(Error::UnboundVariables(names)) if names == ["x", "z"]
+        );
+    }
 
-            if let MathAST::Value(_) = ast {
-                break;
+    fn error_span(input: &str) -> (usize, usize, String) {
+        let err = parse(input).unwrap_err();
+        (err.start, err.end, err.found)
+    }
+
+    #[test]
+    fn test_map_children() {
+        // Only the operands of the root are visited, leaves have none
+        let negate = |operand: MathAST| MathAST::Negate(Box::new(operand));
+        assert_eq!(
+            parse("x * (y + 1) - max(x, 2)")
+                .unwrap()
+                .map_children(negate),
+            parse("-(x * (y + 1)) - -max(x, 2)").unwrap()
+        );
+        assert_eq!(
+            parse("max(x, 2)").unwrap().map_children(negate),
+            MathAST::Call {
+                name: "max".to_string(),
+                args: vec![
+                    negate(MathAST::Var("x".to_string())),
+                    negate(MathAST::Value(2.into()))
+                ],
             }
+        );
+        assert_eq!(
+            parse("x").unwrap().map_children(negate),
+            parse("x").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(error_span(""), (0, 0, "end of input".to_string()));
+        assert_eq!(error_span("(1 + 2"), (6, 6, "end of input".to_string()));
+        assert_eq!(error_span("1 + 2)"), (5, 6, "')'".to_string()));
+        assert_eq!(error_span("1 + * 2"), (4, 5, "'*'".to_string()));
+        assert_eq!(error_span("1 & 2"), (2, 3, "'&'".to_string()));
+        assert_eq!(
+            error_span("1 + 99999999999 2"),
+            (16, 17, "number 2".to_string())
+        );
+        assert_eq!(error_span("1. + 2"), (1, 2, "'.'".to_string()));
+        assert_eq!(error_span("1.5.2"), (3, 4, "'.'".to_string()));
+    }
+
+    #[test]
+    fn test_parse_depth() {
+        let too_deep = |parsed: Result<MathAST, ParseError>| {
+            assert_eq!(
+                parsed.unwrap_err().expected,
+                "at most 256 levels of nesting"
+            )
+        };
+        let nested = |open: &str, leaf: &str, close: &str, n: usize| {
+            format!("{}{}{}", open.repeat(n), leaf, close.repeat(n))
+        };
+
+        // Rejected long before the parser runs out of stack
+        too_deep(parse(&nested("(", "1", ")", 100_000)));
+        too_deep(parse(&nested("-", "1", "", 100_000)));
+        too_deep(parse(&nested("abs(", "1", ")", 100_000)));
+        too_deep(parse(&nested("2 ^ ", "2", "", 100_000)));
+        too_deep(parse_equation(&nested("(", "x", ")", 100_000)).map(|equation| equation.lhs));
+        too_deep(parse_sexpr(&nested("(- ", "1", ")", 100_000)));
+        too_deep(parse_rpn(&nested("", "1", " neg", 100_000)));
+        // Chains deepen the tree without any recursion in the parser
+        too_deep(parse(&vec!["1"; 100_000].join(" + ")));
+        too_deep(parse_rpn(&nested("1", "", " 1 +", 100_000)));
+
+        // Every level counts, down to the leaves
+        let err = parse(&vec!["1"; 257].join(" + ")).unwrap_err();
+        assert_eq!(
+            (err.start, err.end, err.found),
+            (1022, 1023, "'+'".to_string())
+        );
+        assert_eq!(parse(&vec!["1"; 256].join(" + ")).unwrap().depth(), 256);
+        assert_eq!(parse("-abs((x))").unwrap().depth(), 3);
+        assert!(parse(&nested("(", "1", ")", 255)).is_ok());
+        assert!(parse_sexpr(&nested("(- ", "1", ")", 255)).is_ok());
+        assert!(parse_rpn(&nested("1", "", " neg", 255)).is_ok());
+    }
+
+    #[test]
+    fn test_deepest() {
+        // On a thread with the stack a service gives its workers
+        std::thread::Builder::new()
+            .stack_size(crate::service::WORKER_STACK_SIZE)
+            .spawn(|| actix_rt::System::new().block_on(deepest()))
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
+    /// The deepest tree accepted survives every recursive pass and the trip to a service
+    async fn deepest() {
+        let input = vec!["x"; 256].join(" - ");
+        let ast = parse(&input).unwrap();
+        assert_eq!(ast.to_string(), input);
+        assert_eq!(parse_rpn(&ast.rpn().to_string()).unwrap(), ast);
+        assert_eq!(parse_sexpr(&ast.sexpr().to_string()).unwrap(), ast);
+        assert!(ast.latex().to_string().starts_with("x - x"));
+
+        let ast = ast.bind(&[("x".to_string(), 1.into())].into()).unwrap();
+        let evaluator = TestASTEvaluator::default();
+        let ctx = Context::default();
+        let request = CalculationRequest::new(ast.clone(), MathAST::Value(0.into()), &ctx);
+        let request = CalculationRequest::decode(request.encode_to_vec().as_slice()).unwrap();
+        assert_eq!(request.into_args().unwrap().0, ast);
+
+        assert_eq!(
+            evaluator.eval(&ctx, ast.clone()).await.unwrap(),
+            (-254).into()
+        );
+        let (result, step) = evaluator.trace(&ctx, ast).await.unwrap();
+        let response = CalculationResponse::new(result).with_trace(step);
+        let response = CalculationResponse::decode(response.encode_to_vec().as_slice()).unwrap();
+        let (result, step) = response.into_traced_result().unwrap();
+        assert_eq!(result, (-254).into());
+        assert_eq!(step.unwrap().flatten().len(), 255);
+    }
+
+    #[test]
+    fn test_parse_rpn() {
+        assert_eq!(
+            parse_rpn("3 3 + 2 * 4 / 2 -"),
+            parse("( ( (3 + 3)*2) /4) - 2")
+        );
+        assert_eq!(parse_rpn(" 2  -3\t^ neg "), parse("-(2 ^ -3)"));
+        assert_eq!(
+            parse_rpn("x 1 2 y pos max:3 sqrt gcd:2 7 %"),
+            parse("gcd(x, sqrt(max(1, 2, +y))) % 7")
+        );
+        // Signs on values aren't folded so every tree comes back exactly
+        assert_eq!(
+            parse_rpn("3 neg"),
+            Ok(MathAST::Negate(Box::new(MathAST::Value(3.into()))))
+        );
+
+        let error = |input: &str| {
+            let err = parse_rpn(input).unwrap_err();
+            (err.start, err.end, err.expected, err.found)
+        };
+        assert_eq!(
+            error("1 + 2"),
+            (
+                2,
+                3,
+                "2 operands for '+'".to_string(),
+                "1 operand".to_string()
+            )
+        );
+        assert_eq!(
+            error("1 2"),
+            (3, 3, "an operator".to_string(), "end of input".to_string())
+        );
+        assert_eq!(error("").3, "end of input");
+        assert_eq!(
+            error("1 2 &"),
+            (
+                4,
+                5,
+                "a number, operator or parenthesis".to_string(),
+                "'&'".to_string()
+            )
+        );
+        assert_eq!(
+            error("1 2 max"),
+            (
+                4,
+                7,
+                "at least 1 argument to max given as max:n".to_string(),
+                "'max'".to_string()
+            )
+        );
+        assert_eq!(
+            error("1 gcd:1"),
+            (
+                2,
+                7,
+                "at least 2 arguments to gcd".to_string(),
+                "1 argument".to_string()
+            )
+        );
+        assert_eq!(error("1 x:1").2, "a known function");
+        assert_eq!(error("1 2x +").3, "'2x'");
+    }
+
+    #[test]
+    fn test_parse_sexpr() {
+        assert_eq!(
+            parse_sexpr("(- (/ (* (+ 3 3) 2) 4) 2)"),
+            parse("( ( (3 + 3)*2) /4) - 2")
+        );
+        assert_eq!(parse_sexpr("(^ 2 (- (// -3 x)))"), parse("2 ^ -(-3 // x)"));
+        assert_eq!(
+            parse_sexpr("(max 1 (sqrt y) (+ 2))"),
+            Ok(MathAST::Call {
+                name: "max".to_string(),
+                args: vec![
+                    MathAST::Value(1.into()),
+                    parse("sqrt(y)").unwrap(),
+                    MathAST::UnaryPlus(Box::new(MathAST::Value(2.into()))),
+                ]
+            })
+        );
+        assert_eq!(parse_sexpr("  x "), Ok(MathAST::Var("x".to_string())));
+
+        let error = |input: &str| {
+            let err = parse_sexpr(input).unwrap_err();
+            (err.start, err.end, err.expected, err.found)
+        };
+        assert_eq!(
+            error("(+ 1 (* 2 3 4))"),
+            (
+                5,
+                14,
+                "2 arguments to '*'".to_string(),
+                "3 arguments".to_string()
+            )
+        );
+        assert_eq!(error("(- 1 2 3)").2, "1 or 2 arguments to '-'");
+        assert_eq!(error("(sqrt 1 2)").2, "1 argument to sqrt");
+        assert_eq!(
+            error("(f 1)"),
+            (1, 2, "a known function".to_string(), "'f'".to_string())
+        );
+        assert_eq!(
+            error("(+ 1 2"),
+            (
+                6,
+                6,
+                "an operand or ')'".to_string(),
+                "end of input".to_string()
+            )
+        );
+        assert_eq!(error("(+ 1 2) 3").3, "number 3");
+        assert_eq!(error("(1 2)").3, "number 1");
+        assert_eq!(error("(* - 1 2)").3, "'-'");
+    }
+
+    #[test]
+    fn test_alternate_syntax_round_trip() {
+        for input in [
+            "( ( (3 + 3)*2) /4) – 2",
+            "1 - (2 - (3 - 4)) * -(5 + x) / (6 % 7)",
+            "2 ^ 3 ^ -(x - 1) - (2 ^ 3) ^ 4 + (-2) ^ 2 ^ -2",
+            "-(-(x)) - +(y * -z) // 2.50",
+            "max(1, (a + b) * 2, -abs(-c)) ^ sqrt(4) - gcd(4, 6, 8)",
+        ] {
+            let ast = parse(input).unwrap();
+            assert_eq!(
+                parse_rpn(&ast.rpn().to_string()),
+                Ok(ast.clone()),
+                "{}",
+                input
+            );
+            assert_eq!(
+                parse_sexpr(&ast.sexpr().to_string()),
+                Ok(ast.clone()),
+                "{}",
+                input
+            );
         }
+    }
+
+    #[test]
+    fn test_parse_decimal() {
+        assert_eq!(
+            parse("1.50 * 2"),
+            Ok(MathAST::Multiply(
+                Box::new(MathAST::Value(Number::from_literal("1.50"))),
+                value(2)
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_error_render() {
+        let input = "(3 + 3) * * 2";
+        let err = parse(input).unwrap_err();
+
+        assert_eq!(err.expected, "a number or '('");
+        assert_eq!(
+            err.render(input),
+            "error: expected a number or '(', found '*' at 10..11\n  |\n1 | (3 + 3) * * 2\n  |           ^"
+        );
+
+        // Columns count characters rather than bytes
+        let input = "3 – 2 2";
+        let err = parse(input).unwrap_err();
+        assert!(err.render(input).ends_with("\n  |       ^"));
+    }
+
+    #[test]
+    fn test_parse_error_status() {
+        let input = "1 +";
+        let status = parse(input).unwrap_err().to_status(input);
+        assert_eq!(status.code(), Code::InvalidArgument);
+
+        let detail = ParseErrorDetail::decode(status.details()).expect("Detail encoded");
+        assert_eq!((detail.start, detail.end), (3, 3));
+        assert_eq!(detail.found, "end of input");
+        assert_eq!(detail.rendered, status.message());
+    }
+
+    #[test]
+    fn test_expr_round_trip() {
+        let ast = test_value();
+        let expr: Expr = ast.clone().into();
+        assert_eq!(MathAST::try_from(expr).unwrap(), ast);
+
+        let ast = parse("-(1 + 2) ^ +(3 - 1)").unwrap();
+        let expr: Expr = ast.clone().into();
+        assert_eq!(MathAST::try_from(expr).unwrap(), ast);
+
+        let ast = parse("gcd(4 - x, max(6, 8)) + abs(-y)").unwrap();
+        let expr: Expr = ast.clone().into();
+        assert_eq!(MathAST::try_from(expr).unwrap(), ast);
+    }
+
+    #[test]
+    fn test_display() {
+        let rendered = |input: &str| parse(input).unwrap().to_string();
+
+        assert_eq!(rendered("( ( (3 + 3)*2) /4) – 2"), "(3 + 3) * 2 / 4 - 2");
+        assert_eq!(rendered("1 - (2 - 3) - (4 + 5)"), "1 - (2 - 3) - (4 + 5)");
+        assert_eq!(rendered("(1 * 2) / (3 % 4) // 5"), "1 * 2 / (3 % 4) // 5");
+        assert_eq!(rendered("(2 ^ 3) ^ 2 ^ (1 + 1)"), "(2 ^ 3) ^ 2 ^ (1 + 1)");
+        assert_eq!(
+            rendered("-(x ^ 2) * (-y) + -(3 * z)"),
+            "-x ^ 2 * -y + -(3 * z)"
+        );
+        assert_eq!(rendered("(-x) ^ 2 - (-2) ^ x"), "(-x) ^ 2 - (-2) ^ x");
+        assert_eq!(rendered("max(1, (2 * 3), -4)"), "max(1, 2 * 3, -4)");
+        assert_eq!(rendered("+(a) - 1.50"), "+a - 1.50");
+
+        // Fractions are written as divisions
+        let half = Number::Rational(BigRational::new(1.into(), 2.into()));
+        let ast = MathAST::Multiply(
+            Box::new(MathAST::Var("x".to_string())),
+            Box::new(MathAST::Value(half)),
+        );
+        assert_eq!(ast.to_string(), "x * (1/2)");
+        assert_eq!(ast.sexpr().to_string(), "(* x (/ 1 2))");
+        assert_eq!(ast.rpn().to_string(), "x 1 2 / *");
+
+        assert_eq!(
+            parse_equation("2*(x+1) = y").unwrap().to_string(),
+            "2 * (x + 1) = y"
+        );
+    }
+
+    #[test]
+    fn test_alternate_renderers() {
+        let ast = parse("( ( (3 + 3)*2) /4) – 2").unwrap();
+        assert_eq!(ast.parenthesized().to_string(), "(((3 + 3) * 2) / 4) - 2");
+        assert_eq!(ast.sexpr().to_string(), "(- (/ (* (+ 3 3) 2) 4) 2)");
+        assert_eq!(ast.rpn().to_string(), "3 3 + 2 * 4 / 2 -");
+
+        let ast = parse("-sqrt(x) ^ 2 + max(1, -2, +y) // gcd(4, 6)").unwrap();
+        assert_eq!(
+            ast.parenthesized().to_string(),
+            "(-(sqrt(x) ^ 2)) + (max(1, -2, +y) // gcd(4, 6))"
+        );
+        assert_eq!(
+            ast.sexpr().to_string(),
+            "(+ (- (^ (sqrt x) 2)) (// (max 1 -2 (+ y)) (gcd 4 6)))"
+        );
+        assert_eq!(
+            ast.rpn().to_string(),
+            "x sqrt 2 ^ neg 1 -2 y pos max:3 4 6 gcd:2 // +"
+        );
+    }
+
+    #[test]
+    fn test_latex() {
+        let latex = |input: &str| parse(input).unwrap().latex().to_string();
+
+        assert_eq!(
+            latex("( ( (3 + 3)*2) /4) – 2"),
+            "\\frac{\\left(3 + 3\\right) \\cdot 2}{4} - 2"
+        );
+        assert_eq!(
+            latex("(x + 1) ^ (2 * n) - 2 ^ 3 ^ 2"),
+            "\\left(x + 1\\right)^{2 \\cdot n} - 2^{3^{2}}"
+        );
+        assert_eq!(
+            latex("(a / b) ^ 2 * -c - -(d % 2)"),
+            "\\left(\\frac{a}{b}\\right)^{2} \\cdot \\left(-c\\right) - \\left(-\\left(d \\bmod 2\\right)\\right)"
+        );
+        assert_eq!(
+            latex("sqrt(abs(x)) + max(1, floor(y)) // rate_2"),
+            "\\sqrt{\\left|x\\right|} + \\left\\lfloor \\frac{\\max\\left(1, \\left\\lfloor y\\right\\rfloor\\right)}{\\mathrm{rate\\_2}}\\right\\rfloor"
+        );
+
+        let equation = Equation {
+            lhs: parse("x / 2").unwrap(),
+            rhs: MathAST::Value(Number::Rational(BigRational::new((-3).into(), 4.into()))),
+        };
+        assert_eq!(equation.latex(), "\\frac{x}{2} = -\\frac{3}{4}");
+    }
+
+    #[test]
+    fn test_mathml() {
+        let ast = parse("(x + 1) ^ 2 / -3").unwrap();
+        assert_eq!(
+            ast.mathml().to_string(),
+            "<math xmlns=\"http://www.w3.org/1998/Math/MathML\">\
+             <mfrac>\
+             <msup><mrow><mo>(</mo><mrow><mi>x</mi><mo>+</mo><mn>1</mn></mrow><mo>)</mo></mrow><mn>2</mn></msup>\
+             <mrow><mo>\u{2212}</mo><mn>3</mn></mrow>\
+             </mfrac>\
+             </math>"
+        );
+
+        let equation = Equation {
+            lhs: parse("max(a, 2) * sqrt(b)").unwrap(),
+            rhs: MathAST::Value(6.into()),
+        };
+        assert_eq!(
+            equation.mathml(),
+            "<math xmlns=\"http://www.w3.org/1998/Math/MathML\"><mrow>\
+             <mrow>\
+             <mrow><mi>max</mi><mo>\u{2061}</mo><mrow><mo>(</mo><mi>a</mi><mo>,</mo><mn>2</mn><mo>)</mo></mrow></mrow>\
+             <mo>\u{22C5}</mo>\
+             <msqrt><mi>b</mi></msqrt>\
+             </mrow>\
+             <mo>=</mo><mn>6</mn>\
+             </mrow></math>"
+        );
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        for input in [
+            "( ( (3 + 3)*2) /4) – 2",
+            "1 - (2 - (3 - 4)) * -(5 + x) / (6 % 7)",
+            "2 ^ 3 ^ -(x - 1) - (2 ^ 3) ^ 4 + (-2) ^ 2 ^ -2",
+            "-(-(x)) - +(y * -z) // 2",
+            "max(1, (a + b) * 2, -abs(-c)) ^ sqrt(4) - 1.25",
+            "-x ^ 2 * 3 - 4 // -(5 % y) ^ (6 * z)",
+        ] {
+            let ast = parse(input).unwrap();
+            assert_eq!(parse(&ast.to_string()), Ok(ast.clone()), "{}", input);
+            assert_eq!(
+                parse(&ast.parenthesized().to_string()),
+                Ok(ast.clone()),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_expr_invalid() {
+        assert_matches!(MathAST::try_from(Expr { kind: None }), Err(_));
+
+        let missing_operand = Expr {
+            kind: Some(Kind::Binary(Box::new(BinaryExpr {
+                op: Operator::Add.into(),
+                first: Some(Box::new(MathAST::Value(1.into()).into())),
+                second: None,
+            }))),
+        };
+        assert_matches!(MathAST::try_from(missing_operand), Err(_));
+
+        let unknown_operator = Expr {
+            kind: Some(Kind::Binary(Box::new(BinaryExpr {
+                op: 42,
+                first: Some(Box::new(MathAST::Value(1.into()).into())),
+                second: Some(Box::new(MathAST::Value(2.into()).into())),
+            }))),
+        };
+        assert_matches!(
+            MathAST::try_from(unknown_operator),
+            Err(status) if status.code() == Code::InvalidArgument
+        );
+    }
+
+    #[test]
+    fn test_context_invalid() {
+        let ctx = Context::decimal(MAX_SCALE, Rounding::HalfEven);
+        assert_eq!(
+            Context::try_from(ContextMessage::from(ctx.clone())).unwrap(),
+            ctx
+        );
+
+        let too_precise = ContextMessage {
+            scale: MAX_SCALE + 1,
+            ..Context::decimal(2, Rounding::HalfEven).into()
+        };
+        assert_matches!(
+            Context::try_from(too_precise),
+            Err(status) if status.code() == Code::InvalidArgument
+        );
 
+        let unknown_domain = ContextMessage {
+            domain: 42,
+            ..Context::default().into()
+        };
         assert_matches!(
-            ast,
-            MathAST::Value(v) if v == 1
+            Context::try_from(unknown_domain),
+            Err(status) if status.code() == Code::InvalidArgument
         );
     }
 }
@@ -0,0 +1,265 @@
+use super::{
+    lexer::{shallower, tokenize, unexpected, Spanned, Token, MAX_DEPTH},
+    Equation, MathAST, ParseError,
+};
+use crate::function::{lookup, Arity};
+
+const EXPECTED_OPERAND: &str = "a number or '('";
+const EXPECTED_OPERATOR: &str = "an operator or end of input";
+/// Prefix signs bind looser than `^` so `-2 ^ 2` is `-(2 ^ 2)` but tighter than everything else
+const PREFIX_POWER: u8 = 3;
+
+/// Binding power of a binary operator token - higher binds tighter
+pub(super) fn binding_power(token: &Token) -> Option<u8> {
+    match token {
+        Token::Plus | Token::Minus => Some(1),
+        Token::Star | Token::Slash | Token::SlashSlash | Token::Percent => Some(2),
+        Token::Caret => Some(3),
+        _ => None,
+    }
+}
+
+/// Right associative operators group from the right - `2 ^ 3 ^ 2` is `2 ^ (3 ^ 2)`
+fn right_associative(token: &Token) -> bool {
+    matches!(token, Token::Caret)
+}
+
+/// Build the AST node for a binary operator token
+pub(super) fn binary(token: Token, first: MathAST, second: MathAST) -> MathAST {
+    let (first, second) = (Box::new(first), Box::new(second));
+
+    match token {
+        Token::Plus => MathAST::Add(first, second),
+        Token::Minus => MathAST::Subtract(first, second),
+        Token::Star => MathAST::Multiply(first, second),
+        Token::Slash => MathAST::Divide(first, second),
+        Token::SlashSlash => MathAST::FloorDivide(first, second),
+        Token::Percent => MathAST::Remainder(first, second),
+        Token::Caret => MathAST::Power(first, second),
+        _ => unreachable!("binary() is only called for tokens with a binding power"),
+    }
+}
+
+/// Precedence climbing parser over a token stream
+/// A left associative operator only keeps consuming to the right while the next operator binds
+/// strictly tighter than itself, a right associative one also takes operators of its own power
+/// Every subexpression is returned with how deeply it nests - one level per operation, sign and
+/// pair of parentheses - so nothing deeper than MAX_DEPTH is ever built
+struct Parser<'a> {
+    input: &'a str,
+    tokens: Vec<Spanned>,
+    pos: usize,
+    /// Calls to expression() in progress, which bound the parser's own recursion
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|s| &s.token)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.peek().cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn unexpected(&self, pos: usize, expected: &str) -> ParseError {
+        unexpected(self.input, &self.tokens, pos, expected)
+    }
+
+    /// One level deeper than `depth` for the token at `pos`, unless that's too deep
+    fn nest(&self, pos: usize, depth: usize) -> Result<usize, ParseError> {
+        if depth < MAX_DEPTH {
+            Ok(depth + 1)
+        } else {
+            Err(self.unexpected(pos, &shallower()))
+        }
+    }
+
+    fn expression(&mut self, min_power: u8) -> Result<(MathAST, usize), ParseError> {
+        self.depth = self.nest(self.pos, self.depth)?;
+        let expression = self.operations(min_power);
+        self.depth -= 1;
+        expression
+    }
+
+    fn operations(&mut self, min_power: u8) -> Result<(MathAST, usize), ParseError> {
+        let (mut lhs, mut depth) = self.unary()?;
+
+        while let Some(power) = self.peek().and_then(binding_power) {
+            if power < min_power {
+                break;
+            }
+            let pos = self.pos;
+            let op = self.next().expect("peeked an operator");
+
+            let min_rhs = if right_associative(&op) {
+                power
+            } else {
+                power + 1
+            };
+            let (rhs, rhs_depth) = self.expression(min_rhs)?;
+            depth = self.nest(pos, depth.max(rhs_depth))?;
+            lhs = binary(op, lhs, rhs);
+        }
+
+        Ok((lhs, depth))
+    }
+
+    /// Operand with any prefix signs - a sign directly on a literal becomes part of the literal
+    fn unary(&mut self) -> Result<(MathAST, usize), ParseError> {
+        let pos = self.pos;
+        let sign = match self.peek() {
+            Some(sign @ (Token::Minus | Token::Plus)) => sign.clone(),
+            _ => return self.primary(),
+        };
+        self.next();
+
+        let (operand, depth) = self.expression(PREFIX_POWER)?;
+        let depth = self.nest(pos, depth)?;
+        let signed = match (sign, operand) {
+            (Token::Minus, MathAST::Value(v)) => MathAST::Value(-v),
+            (Token::Minus, operand) => MathAST::Negate(Box::new(operand)),
+            (_, MathAST::Value(v)) => MathAST::Value(v),
+            (_, operand) => MathAST::UnaryPlus(Box::new(operand)),
+        };
+        Ok((signed, depth))
+    }
+
+    fn primary(&mut self) -> Result<(MathAST, usize), ParseError> {
+        let pos = self.pos;
+
+        match self.next() {
+            Some(Token::Number(v)) => Ok((MathAST::Value(v), 1)),
+            // Only a name directly followed by `(` is a call, anything else is a variable
+            Some(Token::Ident(name)) if self.peek() == Some(&Token::LParen) => self.call(pos, name),
+            Some(Token::Ident(name)) => Ok((MathAST::Var(name), 1)),
+            Some(Token::LParen) => {
+                let (inner, depth) = self.expression(0)?;
+                let depth = self.nest(pos, depth)?;
+                let close = self.pos;
+                match self.next() {
+                    Some(Token::RParen) => Ok((inner, depth)),
+                    _ => Err(self.unexpected(close, "an operator or ')'")),
+                }
+            }
+            _ => Err(self.unexpected(pos, EXPECTED_OPERAND)),
+        }
+    }
+
+    fn equation(&mut self) -> Result<Equation, ParseError> {
+        let (lhs, _) = self.expression(0)?;
+        let pos = self.pos;
+        if self.next() != Some(Token::Equals) {
+            return Err(self.unexpected(pos, "an operator or '='"));
+        }
+        let (rhs, _) = self.expression(0)?;
+
+        Ok(Equation { lhs, rhs })
+    }
+
+    /// `name(arg, ...)` once the name at `name_pos` has been consumed and `(` peeked
+    /// Names and arity are checked against the function registry here so mistakes are reported
+    /// against the input rather than by the Functions service
+    fn call(&mut self, name_pos: usize, name: String) -> Result<(MathAST, usize), ParseError> {
+        let function =
+            lookup(&name).ok_or_else(|| self.unexpected(name_pos, "a known function"))?;
+        self.next();
+
+        let mut args = Vec::new();
+        let mut depth = 0;
+        if self.peek() == Some(&Token::RParen) {
+            self.next();
+        } else {
+            loop {
+                let (arg, arg_depth) = self.expression(0)?;
+                args.push(arg);
+                depth = depth.max(arg_depth);
+                let pos = self.pos;
+                match self.next() {
+                    Some(Token::Comma) => continue,
+                    Some(Token::RParen) => break,
+                    _ => return Err(self.unexpected(pos, "an operator, ',' or ')'")),
+                }
+            }
+        }
+
+        if !function.arity.accepts(args.len()) {
+            return Err(ParseError {
+                start: self.tokens[name_pos].start,
+                end: self.tokens[self.pos - 1].end,
+                expected: format!("{} to {}", function.arity, name),
+                found: Arity::Exactly(args.len()).to_string(),
+            });
+        }
+
+        let depth = self.nest(name_pos, depth)?;
+        Ok((MathAST::Call { name, args }, depth))
+    }
+}
+
+/// Parse a conventional infix equation such as `( ( (3 + 3)*2) /4) - 2`
+pub(crate) fn parse_infix(input: &str) -> Result<MathAST, ParseError> {
+    let mut parser = Parser {
+        input,
+        tokens: tokenize(input)?,
+        pos: 0,
+        depth: 0,
+    };
+
+    let (ast, _) = parser.expression(0)?;
+
+    if parser.pos < parser.tokens.len() {
+        Err(parser.unexpected(parser.pos, EXPECTED_OPERATOR))
+    } else {
+        Ok(ast)
+    }
+}
+
+/// Parse `lhs = rhs` where both sides are infix expressions
+pub(crate) fn parse_infix_equation(input: &str) -> Result<Equation, ParseError> {
+    let mut parser = Parser {
+        input,
+        tokens: tokenize(input)?,
+        pos: 0,
+        depth: 0,
+    };
+
+    let equation = parser.equation()?;
+
+    if parser.pos < parser.tokens.len() {
+        Err(parser.unexpected(parser.pos, EXPECTED_OPERATOR))
+    } else {
+        Ok(equation)
+    }
+}
+
+/// Parse `{lhs = rhs, ...}` - a brace delimited list of at least one equation
+pub(crate) fn parse_infix_system(input: &str) -> Result<Vec<Equation>, ParseError> {
+    let mut parser = Parser {
+        input,
+        tokens: tokenize(input)?,
+        pos: 0,
+        depth: 0,
+    };
+
+    if parser.next() != Some(Token::LBrace) {
+        return Err(parser.unexpected(0, "'{'"));
+    }
+    let mut equations = vec![parser.equation()?];
+    loop {
+        let pos = parser.pos;
+        match parser.next() {
+            Some(Token::Comma) => equations.push(parser.equation()?),
+            Some(Token::RBrace) => break,
+            _ => return Err(parser.unexpected(pos, "an operator, ',' or '}'")),
+        }
+    }
+
+    if parser.pos < parser.tokens.len() {
+        Err(parser.unexpected(parser.pos, "end of input"))
+    } else {
+        Ok(equations)
+    }
+}
@@ -0,0 +1,180 @@
+use std::fmt;
+
+use super::ParseError;
+use crate::number::Number;
+
+/// Tokens recognised in an infix equation string
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Token {
+    Number(Number),
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    SlashSlash,
+    Percent,
+    Caret,
+    LParen,
+    RParen,
+    /// Braces only delimit systems of equations
+    LBrace,
+    RBrace,
+    Comma,
+    /// Separates the two sides of an equation
+    Equals,
+    /// Function or variable name
+    Ident(String),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Number(v) => write!(f, "number {}", v),
+            Token::Plus => write!(f, "'+'"),
+            Token::Minus => write!(f, "'-'"),
+            Token::Star => write!(f, "'*'"),
+            Token::Slash => write!(f, "'/'"),
+            Token::SlashSlash => write!(f, "'//'"),
+            Token::Percent => write!(f, "'%'"),
+            Token::Caret => write!(f, "'^'"),
+            Token::LParen => write!(f, "'('"),
+            Token::RParen => write!(f, "')'"),
+            Token::LBrace => write!(f, "'{{'"),
+            Token::RBrace => write!(f, "'}}'"),
+            Token::Comma => write!(f, "','"),
+            Token::Equals => write!(f, "'='"),
+            Token::Ident(name) => write!(f, "'{}'", name),
+        }
+    }
+}
+
+/// A token along with the byte range it was read from
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Spanned {
+    pub(crate) token: Token,
+    pub(crate) start: usize,
+    pub(crate) end: usize,
+}
+
+/// Split an input string into tokens - whitespace is skipped
+pub(crate) fn tokenize(input: &str) -> Result<Vec<Spanned>, ParseError> {
+    let mut tokens = Vec::new();
+    let mut chars = input.char_indices().peekable();
+
+    while let Some((start, c)) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '+' => Token::Plus,
+            // The exercise text uses an en dash for subtraction so accept it alongside '-'
+            '-' | '–' => Token::Minus,
+            '*' => Token::Star,
+            '/' if input[start + 1..].starts_with('/') => {
+                chars.next();
+                tokens.push(Spanned {
+                    token: Token::SlashSlash,
+                    start,
+                    end: start + 2,
+                });
+                continue;
+            }
+            '/' => Token::Slash,
+            '%' => Token::Percent,
+            '^' => Token::Caret,
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            '{' => Token::LBrace,
+            '}' => Token::RBrace,
+            ',' => Token::Comma,
+            '=' => Token::Equals,
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let mut end = start + c.len_utf8();
+                while let Some((i, d)) = chars.peek().copied() {
+                    if !d.is_ascii_alphanumeric() && d != '_' {
+                        break;
+                    }
+                    end = i + d.len_utf8();
+                    chars.next();
+                }
+
+                tokens.push(Spanned {
+                    token: Token::Ident(input[start..end].to_string()),
+                    start,
+                    end,
+                });
+                continue;
+            }
+            // Digits with at most one decimal point, which must be followed by a digit
+            c if c.is_ascii_digit() => {
+                let mut end = start + c.len_utf8();
+                let mut point = false;
+                while let Some((i, d)) = chars.peek().copied() {
+                    let fraction = !point
+                        && d == '.'
+                        && input[i + 1..].starts_with(|n: char| n.is_ascii_digit());
+                    if !d.is_ascii_digit() && !fraction {
+                        break;
+                    }
+                    point |= fraction;
+                    end = i + d.len_utf8();
+                    chars.next();
+                }
+
+                tokens.push(Spanned {
+                    token: Token::Number(Number::from_literal(&input[start..end])),
+                    start,
+                    end,
+                });
+                continue;
+            }
+            c => {
+                return Err(ParseError {
+                    start,
+                    end: start + c.len_utf8(),
+                    expected: "a number, operator or parenthesis".to_string(),
+                    found: format!("'{}'", c),
+                })
+            }
+        };
+
+        tokens.push(Spanned {
+            token,
+            start,
+            end: start + c.len_utf8(),
+        });
+    }
+
+    Ok(tokens)
+}
+
+/// Deepest nesting of operations, signs and parentheses a parser accepts - every pass over a tree,
+/// from evaluating it to decoding it off the wire, recurses once per level and this keeps them all
+/// well within the stack of a service worker
+pub(crate) const MAX_DEPTH: usize = 256;
+
+/// What a parser expected of input nested deeper than MAX_DEPTH
+pub(crate) fn shallower() -> String {
+    format!("at most {} levels of nesting", MAX_DEPTH)
+}
+
+/// Error for the token at `pos` (or the end of input) not being what we expected
+pub(crate) fn unexpected(
+    input: &str,
+    tokens: &[Spanned],
+    pos: usize,
+    expected: &str,
+) -> ParseError {
+    match tokens.get(pos) {
+        Some(spanned) => ParseError {
+            start: spanned.start,
+            end: spanned.end,
+            expected: expected.to_string(),
+            found: spanned.token.to_string(),
+        },
+        None => ParseError {
+            start: input.len(),
+            end: input.len(),
+            expected: expected.to_string(),
+            found: "end of input".to_string(),
+        },
+    }
+}
@@ -0,0 +1,203 @@
+use std::fmt;
+
+use super::{Equation, MathAST};
+use crate::{
+    function::{lookup, Arity},
+    number::Number,
+};
+
+/// How tightly a node holds together when written infix - mirrors the binding powers in infix.rs
+/// Prefix signs sit between products and powers: `-x ^ 2` is `-(x ^ 2)` but `-x * 2` is `(-x) * 2`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(super) enum Precedence {
+    Sum,
+    Product,
+    Prefix,
+    Power,
+    /// Stacked fractions - only typeset, a power of one still needs parentheses
+    Fraction,
+    Atom,
+}
+
+pub(super) fn precedence(ast: &MathAST) -> Precedence {
+    match ast {
+        // Fractions are written as a division and signed literals with their sign
+        MathAST::Value(Number::Rational(v)) if !v.is_integer() => Precedence::Product,
+        MathAST::Value(v) if v.is_negative() => Precedence::Prefix,
+        MathAST::Value(_) | MathAST::Var(_) | MathAST::Call { .. } => Precedence::Atom,
+        MathAST::Negate(_) | MathAST::UnaryPlus(_) => Precedence::Prefix,
+        MathAST::Power(..) => Precedence::Power,
+        MathAST::Multiply(..)
+        | MathAST::Divide(..)
+        | MathAST::Remainder(..)
+        | MathAST::FloorDivide(..) => Precedence::Product,
+        MathAST::Add(..) | MathAST::Subtract(..) => Precedence::Sum,
+    }
+}
+
+/// Whether an operand is wrapped in parentheses
+#[derive(Clone, Copy)]
+enum Parens {
+    /// Only where the parser would otherwise group it differently
+    Minimal,
+    /// Around every operation
+    Full,
+}
+
+fn write_infix(f: &mut fmt::Formatter<'_>, ast: &MathAST, parens: Parens) -> fmt::Result {
+    // `needs` is the loosest precedence the operand can have without parentheses
+    let operand = |f: &mut fmt::Formatter<'_>, operand: &MathAST, needs: Precedence| {
+        let wrap = match parens {
+            Parens::Minimal => precedence(operand) < needs,
+            Parens::Full => precedence(operand) < Precedence::Atom,
+        };
+        if wrap {
+            write!(f, "(")?;
+            write_infix(f, operand, parens)?;
+            write!(f, ")")
+        } else {
+            write_infix(f, operand, parens)
+        }
+    };
+
+    match ast {
+        MathAST::Value(v) => write!(f, "{}", v),
+        MathAST::Var(name) => write!(f, "{}", name),
+        MathAST::Call { name, args } => {
+            write!(f, "{}(", name)?;
+            for (i, arg) in args.iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write_infix(f, arg, parens)?;
+            }
+            write!(f, ")")
+        }
+        // A sign takes everything up to the next operator looser than `^`
+        MathAST::Negate(v) => {
+            write!(f, "-")?;
+            operand(f, v, Precedence::Prefix)
+        }
+        MathAST::UnaryPlus(v) => {
+            write!(f, "+")?;
+            operand(f, v, Precedence::Prefix)
+        }
+        _ => {
+            let (op, first, second) = ast.as_binary().expect("every other node is binary");
+            let own = precedence(ast);
+            // `^` groups from the right so a power base needs parentheses where an exponent
+            // doesn't, every other operator groups from the left
+            let (first_needs, second_needs) = match own {
+                Precedence::Power => (Precedence::Atom, Precedence::Prefix),
+                Precedence::Sum => (Precedence::Sum, Precedence::Product),
+                _ => (Precedence::Product, Precedence::Prefix),
+            };
+
+            operand(f, first, first_needs)?;
+            write!(f, " {} ", op.symbol())?;
+            operand(f, second, second_needs)
+        }
+    }
+}
+
+/// Conventional infix with only the parentheses the parser needs to rebuild the same tree
+/// A sign applied to a literal is read back as part of the literal and fractions as divisions
+impl fmt::Display for MathAST {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_infix(f, self, Parens::Minimal)
+    }
+}
+
+impl fmt::Display for Equation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} = {}", self.lhs, self.rhs)
+    }
+}
+
+/// Infix with every operation below the root in parentheses - see MathAST::parenthesized
+pub struct Parenthesized<'a>(&'a MathAST);
+
+impl fmt::Display for Parenthesized<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_infix(f, self.0, Parens::Full)
+    }
+}
+
+/// Prefix notation - see MathAST::sexpr
+pub struct SExpr<'a>(&'a MathAST);
+
+impl fmt::Display for SExpr<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            MathAST::Value(Number::Rational(v)) if !v.is_integer() => {
+                write!(f, "(/ {} {})", v.numer(), v.denom())
+            }
+            MathAST::Value(v) => write!(f, "{}", v),
+            MathAST::Var(name) => write!(f, "{}", name),
+            MathAST::Negate(v) => write!(f, "(- {})", SExpr(v)),
+            MathAST::UnaryPlus(v) => write!(f, "(+ {})", SExpr(v)),
+            MathAST::Call { name, args } => {
+                write!(f, "({}", name)?;
+                for arg in args {
+                    write!(f, " {}", SExpr(arg))?;
+                }
+                write!(f, ")")
+            }
+            ast => {
+                let (op, first, second) = ast.as_binary().expect("every other node is binary");
+                write!(f, "({} {} {})", op.symbol(), SExpr(first), SExpr(second))
+            }
+        }
+    }
+}
+
+/// Postfix notation - see MathAST::rpn
+pub struct Rpn<'a>(&'a MathAST);
+
+impl fmt::Display for Rpn<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            MathAST::Value(Number::Rational(v)) if !v.is_integer() => {
+                write!(f, "{} {} /", v.numer(), v.denom())
+            }
+            MathAST::Value(v) => write!(f, "{}", v),
+            MathAST::Var(name) => write!(f, "{}", name),
+            MathAST::Negate(v) => write!(f, "{} neg", Rpn(v)),
+            MathAST::UnaryPlus(v) => write!(f, "{} pos", Rpn(v)),
+            MathAST::Call { name, args } => {
+                for arg in args {
+                    write!(f, "{} ", Rpn(arg))?;
+                }
+                match lookup(name).map(|function| &function.arity) {
+                    Some(Arity::Exactly(_)) => write!(f, "{}", name),
+                    _ => write!(f, "{}:{}", name, args.len()),
+                }
+            }
+            ast => {
+                let (op, first, second) = ast.as_binary().expect("every other node is binary");
+                write!(f, "{} {} {}", Rpn(first), Rpn(second), op.symbol())
+            }
+        }
+    }
+}
+
+impl MathAST {
+    /// Infix with every operation in parentheses apart from the outermost, the exercise
+    /// equation is written `(((3 + 3) * 2) / 4) - 2`
+    pub fn parenthesized(&self) -> Parenthesized<'_> {
+        Parenthesized(self)
+    }
+
+    /// Lisp style prefix notation like `(- (/ (* (+ 3 3) 2) 4) 2)`
+    /// Signs are `(- x)` and `(+ x)`, calls are `(max 1 2)`
+    pub fn sexpr(&self) -> SExpr<'_> {
+        SExpr(self)
+    }
+
+    /// Postfix token stream like `3 3 + 2 * 4 / 2 -`
+    /// Signs are `neg` and `pos`, calls take their arguments off the stack - the functions that
+    /// accept any number of arguments say how many after a colon, `1 2 3 max:3`
+    pub fn rpn(&self) -> Rpn<'_> {
+        Rpn(self)
+    }
+}
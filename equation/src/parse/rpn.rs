@@ -0,0 +1,186 @@
+use super::{
+    infix::{binary, binding_power},
+    lexer::{shallower, tokenize, Token, MAX_DEPTH},
+    MathAST, ParseError,
+};
+use crate::function::{lookup, Arity};
+
+const EXPECTED_WORD: &str = "a number, variable, operator or function";
+
+/// What a single word of postfix input does to the stack
+enum Word {
+    /// Pushed as it is
+    Operand(MathAST),
+    Binary(Token),
+    Negate,
+    UnaryPlus,
+    Call {
+        name: String,
+        arity: usize,
+    },
+}
+
+impl Word {
+    /// How many operands it takes off the stack
+    fn arity(&self) -> usize {
+        match self {
+            Word::Operand(_) => 0,
+            Word::Negate | Word::UnaryPlus => 1,
+            Word::Binary(_) => 2,
+            Word::Call { arity, .. } => *arity,
+        }
+    }
+
+    /// The node pushed in place of its operands
+    fn build(self, mut operands: Vec<MathAST>) -> MathAST {
+        match self {
+            Word::Operand(ast) => ast,
+            Word::Binary(op) => {
+                let second = operands.pop().expect("two operands");
+                let first = operands.pop().expect("two operands");
+                binary(op, first, second)
+            }
+            Word::Negate => MathAST::Negate(Box::new(operands.pop().expect("one operand"))),
+            Word::UnaryPlus => MathAST::UnaryPlus(Box::new(operands.pop().expect("one operand"))),
+            Word::Call { name, .. } => MathAST::Call {
+                name,
+                args: operands,
+            },
+        }
+    }
+}
+
+/// Whitespace separated words along with the byte offset each starts at
+fn words(input: &str) -> Vec<(usize, &str)> {
+    let mut words = Vec::new();
+    let mut start = None;
+
+    for (i, c) in input.char_indices() {
+        match (c.is_whitespace(), start) {
+            (true, Some(s)) => {
+                words.push((s, &input[s..i]));
+                start = None;
+            }
+            (false, None) => start = Some(i),
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        words.push((s, &input[s..]));
+    }
+    words
+}
+
+/// Classify the word starting at byte `start` - errors are reported against the whole input
+fn word(start: usize, word: &str) -> Result<Word, ParseError> {
+    let error = |expected: String, found: String| ParseError {
+        start,
+        end: start + word.len(),
+        expected,
+        found,
+    };
+
+    // Functions that take any number of arguments are written `max:3`
+    let (body, count) = match word.split_once(':') {
+        Some((name, count)) => match count.parse::<usize>() {
+            Ok(count) => (name, Some(count)),
+            Err(_) => {
+                return Err(error(
+                    "an argument count".to_string(),
+                    format!("'{}'", count),
+                ))
+            }
+        },
+        None => (word, None),
+    };
+    let tokens: Vec<Token> = tokenize(body)
+        .map_err(|e| ParseError {
+            start: start + e.start,
+            end: start + e.end,
+            ..e
+        })?
+        .into_iter()
+        .map(|spanned| spanned.token)
+        .collect();
+
+    match (tokens.as_slice(), count) {
+        ([Token::Number(v)], None) | ([Token::Plus, Token::Number(v)], None) => {
+            Ok(Word::Operand(MathAST::Value(v.clone())))
+        }
+        ([Token::Minus, Token::Number(v)], None) => Ok(Word::Operand(MathAST::Value(-v.clone()))),
+        ([op], None) if binding_power(op).is_some() => Ok(Word::Binary(op.clone())),
+        ([Token::Ident(name)], None) if name == "neg" => Ok(Word::Negate),
+        ([Token::Ident(name)], None) if name == "pos" => Ok(Word::UnaryPlus),
+        ([Token::Ident(name)], count) => match (lookup(name), count) {
+            (None, None) => Ok(Word::Operand(MathAST::Var(name.clone()))),
+            (None, Some(_)) => Err(error("a known function".to_string(), format!("'{}'", body))),
+            (Some(function), None) => match function.arity {
+                Arity::Exactly(arity) => Ok(Word::Call {
+                    name: name.clone(),
+                    arity,
+                }),
+                arity => Err(error(
+                    format!("{} to {} given as {}:n", arity, name, name),
+                    format!("'{}'", name),
+                )),
+            },
+            (Some(function), Some(count)) if function.arity.accepts(count) => Ok(Word::Call {
+                name: name.clone(),
+                arity: count,
+            }),
+            (Some(function), Some(count)) => Err(error(
+                format!("{} to {}", function.arity, name),
+                Arity::Exactly(count).to_string(),
+            )),
+        },
+        _ => Err(error(EXPECTED_WORD.to_string(), format!("'{}'", word))),
+    }
+}
+
+fn operands(n: usize) -> String {
+    let plural = if n == 1 { "" } else { "s" };
+    format!("{} operand{}", n, plural)
+}
+
+/// Parse a postfix token stream such as `3 3 + 2 * 4 / 2 -` - see MathAST::rpn for the words
+/// Words are separated by whitespace so `-3` is a literal where `- 3` is an operator
+pub(crate) fn parse_rpn(input: &str) -> Result<MathAST, ParseError> {
+    // Every tree is stacked along with its depth so none deeper than MAX_DEPTH is built
+    let mut stack: Vec<(MathAST, usize)> = Vec::new();
+
+    for (start, text) in words(input) {
+        let word = word(start, text)?;
+
+        let arity = word.arity();
+        if stack.len() < arity {
+            return Err(ParseError {
+                start,
+                end: start + text.len(),
+                expected: format!("{} for '{}'", operands(arity), text),
+                found: operands(stack.len()),
+            });
+        }
+        let (operands, depths): (Vec<MathAST>, Vec<usize>) =
+            stack.split_off(stack.len() - arity).into_iter().unzip();
+        let depth = depths.into_iter().max().unwrap_or(0) + 1;
+        if depth > MAX_DEPTH {
+            return Err(ParseError {
+                start,
+                end: start + text.len(),
+                expected: shallower(),
+                found: format!("'{}'", text),
+            });
+        }
+        stack.push((word.build(operands), depth));
+    }
+
+    match stack.len() {
+        1 => Ok(stack.pop().expect("one tree left").0),
+        n => Err(ParseError {
+            start: input.len(),
+            end: input.len(),
+            expected: if n == 0 { EXPECTED_WORD } else { "an operator" }.to_string(),
+            found: "end of input".to_string(),
+        }),
+    }
+}
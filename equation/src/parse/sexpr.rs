@@ -0,0 +1,147 @@
+use super::{
+    infix::{binary, binding_power},
+    lexer::{shallower, tokenize, unexpected, Spanned, Token, MAX_DEPTH},
+    MathAST, ParseError,
+};
+use crate::function::{lookup, Arity};
+
+const EXPECTED_OPERAND: &str = "a number, variable or '('";
+
+/// Recursive descent over `(op operand...)` lists
+struct Parser<'a> {
+    input: &'a str,
+    tokens: Vec<Spanned>,
+    pos: usize,
+    /// Lists open around the current token - each is a level of the tree above its leaves
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).map(|s| s.token.clone());
+        self.pos += 1;
+        token
+    }
+
+    fn unexpected(&self, pos: usize, expected: &str) -> ParseError {
+        unexpected(self.input, &self.tokens, pos, expected)
+    }
+
+    /// Whether the token at `pos` directly follows the one before it with no space between
+    fn touches_previous(&self, pos: usize) -> bool {
+        match (self.tokens.get(pos - 1), self.tokens.get(pos)) {
+            (Some(previous), Some(current)) => previous.end == current.start,
+            _ => false,
+        }
+    }
+
+    fn expression(&mut self) -> Result<MathAST, ParseError> {
+        let pos = self.pos;
+
+        match self.next() {
+            // A sign written against a number is part of the literal, `(- 3)` is an operation
+            Some(sign @ (Token::Minus | Token::Plus)) if self.touches_previous(pos + 1) => {
+                match self.next() {
+                    Some(Token::Number(v)) if sign == Token::Minus => Ok(MathAST::Value(-v)),
+                    Some(Token::Number(v)) => Ok(MathAST::Value(v)),
+                    _ => Err(self.unexpected(pos, EXPECTED_OPERAND)),
+                }
+            }
+            Some(Token::Number(v)) => Ok(MathAST::Value(v)),
+            Some(Token::Ident(name)) => Ok(MathAST::Var(name)),
+            Some(Token::LParen) if self.depth + 1 == MAX_DEPTH => {
+                Err(self.unexpected(pos, &shallower()))
+            }
+            Some(Token::LParen) => {
+                self.depth += 1;
+                let list = self.list(pos);
+                self.depth -= 1;
+                list
+            }
+            _ => Err(self.unexpected(pos, EXPECTED_OPERAND)),
+        }
+    }
+
+    /// `(op operand...)` once the `(` at `open` has been consumed
+    /// The operator or function is checked against its number of operands once the list closes
+    fn list(&mut self, open: usize) -> Result<MathAST, ParseError> {
+        let head_pos = self.pos;
+        let head = match self.next() {
+            Some(op) if binding_power(&op).is_some() => op,
+            Some(Token::Ident(name)) if lookup(&name).is_some() => Token::Ident(name),
+            Some(Token::Ident(_)) => return Err(self.unexpected(head_pos, "a known function")),
+            _ => return Err(self.unexpected(head_pos, "an operator or function")),
+        };
+
+        let mut operands = Vec::new();
+        loop {
+            match self.tokens.get(self.pos).map(|s| &s.token) {
+                Some(Token::RParen) => break,
+                None => return Err(self.unexpected(self.pos, "an operand or ')'")),
+                Some(_) => operands.push(self.expression()?),
+            }
+        }
+        self.next();
+
+        let (start, end, count) = (
+            self.tokens[open].start,
+            self.tokens[self.pos - 1].end,
+            operands.len(),
+        );
+        let arity_error = |expected: String| ParseError {
+            start,
+            end,
+            expected,
+            found: Arity::Exactly(count).to_string(),
+        };
+
+        match head {
+            Token::Ident(name) => {
+                let function = lookup(&name).expect("checked when the list opened");
+                if !function.arity.accepts(operands.len()) {
+                    return Err(arity_error(format!("{} to {}", function.arity, name)));
+                }
+                Ok(MathAST::Call {
+                    name,
+                    args: operands,
+                })
+            }
+            // A lone operand makes `-` and `+` signs
+            Token::Minus | Token::Plus if operands.len() == 1 => {
+                let operand = Box::new(operands.pop().expect("one operand"));
+                Ok(match head {
+                    Token::Minus => MathAST::Negate(operand),
+                    _ => MathAST::UnaryPlus(operand),
+                })
+            }
+            op if operands.len() == 2 => {
+                let second = operands.pop().expect("two operands");
+                let first = operands.pop().expect("two operands");
+                Ok(binary(op, first, second))
+            }
+            op @ (Token::Minus | Token::Plus) => {
+                Err(arity_error(format!("1 or 2 arguments to {}", op)))
+            }
+            op => Err(arity_error(format!("{} to {}", Arity::Exactly(2), op))),
+        }
+    }
+}
+
+/// Parse a Lisp style prefix expression such as `(- (/ (* (+ 3 3) 2) 4) 2)`
+/// See MathAST::sexpr for the forms - a sign against a number like `-3` is part of the literal
+pub(crate) fn parse_sexpr(input: &str) -> Result<MathAST, ParseError> {
+    let mut parser = Parser {
+        input,
+        tokens: tokenize(input)?,
+        pos: 0,
+        depth: 0,
+    };
+
+    let ast = parser.expression()?;
+
+    if parser.pos < parser.tokens.len() {
+        Err(parser.unexpected(parser.pos, "end of input"))
+    } else {
+        Ok(ast)
+    }
+}
@@ -0,0 +1,302 @@
+use std::fmt;
+
+use super::{
+    render::{self, Precedence},
+    Equation, MathAST,
+};
+use crate::number::Number;
+
+/// Precedence once divisions are drawn as fractions and floors as brackets
+fn precedence(ast: &MathAST) -> Precedence {
+    match ast {
+        MathAST::Value(v) if v.is_negative() => Precedence::Prefix,
+        MathAST::Value(Number::Rational(v)) if !v.is_integer() => Precedence::Fraction,
+        MathAST::Divide(..) => Precedence::Fraction,
+        MathAST::FloorDivide(..) => Precedence::Atom,
+        ast => render::precedence(ast),
+    }
+}
+
+/// Whether an operand is drawn in parentheses - unlike plain text a sign straight after an
+/// operator is always bracketed, `2 \cdot (-3)` rather than `2 \cdot -3`
+fn wrap(operand: &MathAST, needs: Precedence, after_operator: bool) -> bool {
+    let own = precedence(operand);
+    own < needs || (after_operator && own == Precedence::Prefix)
+}
+
+/// Loosest precedence each operand of a binary node can have without parentheses
+/// Fractions and exponents are laid out apart from their surroundings so take anything
+fn operand_needs(ast: &MathAST) -> (Precedence, Precedence) {
+    match ast {
+        MathAST::Add(..) | MathAST::Subtract(..) => (Precedence::Sum, Precedence::Product),
+        MathAST::Power(..) => (Precedence::Atom, Precedence::Sum),
+        MathAST::Divide(..) | MathAST::FloorDivide(..) => (Precedence::Sum, Precedence::Sum),
+        _ => (Precedence::Product, Precedence::Prefix),
+    }
+}
+
+/// LaTeX math mode markup - see MathAST::latex
+pub struct Latex<'a>(&'a MathAST);
+
+impl Latex<'_> {
+    fn operand(
+        f: &mut fmt::Formatter<'_>,
+        operand: &MathAST,
+        needs: Precedence,
+        after_operator: bool,
+    ) -> fmt::Result {
+        if wrap(operand, needs, after_operator) {
+            write!(f, "\\left({}\\right)", Latex(operand))
+        } else {
+            write!(f, "{}", Latex(operand))
+        }
+    }
+
+    fn number(f: &mut fmt::Formatter<'_>, v: &Number) -> fmt::Result {
+        if v.is_negative() {
+            write!(f, "-")?;
+            return Self::number(f, &-v.clone());
+        }
+        match v {
+            Number::Rational(v) if !v.is_integer() => {
+                write!(f, "\\frac{{{}}}{{{}}}", v.numer(), v.denom())
+            }
+            Number::F64(v) if v.is_nan() => write!(f, "\\mathrm{{NaN}}"),
+            Number::F64(v) if v.is_infinite() => write!(f, "\\infty"),
+            v => write!(f, "{}", v),
+        }
+    }
+
+    fn call(f: &mut fmt::Formatter<'_>, name: &str, args: &[MathAST]) -> fmt::Result {
+        let delimited = |f: &mut fmt::Formatter<'_>, open: &str, close: &str| {
+            write!(f, "\\left{}", open)?;
+            for (i, arg) in args.iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{}", Latex(arg))?;
+            }
+            write!(f, "\\right{}", close)
+        };
+
+        match name {
+            "sqrt" => {
+                write!(f, "\\sqrt{{")?;
+                for arg in args {
+                    write!(f, "{}", Latex(arg))?;
+                }
+                write!(f, "}}")
+            }
+            "abs" => delimited(f, "|", "|"),
+            "floor" => delimited(f, "\\lfloor ", "\\rfloor"),
+            "ceil" => delimited(f, "\\lceil ", "\\rceil"),
+            "min" | "max" | "gcd" => {
+                write!(f, "\\{}", name)?;
+                delimited(f, "(", ")")
+            }
+            _ => {
+                write!(f, "\\operatorname{{{}}}", name)?;
+                delimited(f, "(", ")")
+            }
+        }
+    }
+}
+
+impl fmt::Display for Latex<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            MathAST::Value(v) => Self::number(f, v),
+            MathAST::Var(name) if name.len() == 1 => write!(f, "{}", name),
+            MathAST::Var(name) => write!(f, "\\mathrm{{{}}}", name.replace('_', "\\_")),
+            MathAST::Call { name, args } => Self::call(f, name, args),
+            MathAST::Negate(v) => {
+                write!(f, "-")?;
+                Self::operand(f, v, Precedence::Prefix, true)
+            }
+            MathAST::UnaryPlus(v) => {
+                write!(f, "+")?;
+                Self::operand(f, v, Precedence::Prefix, true)
+            }
+            ast => {
+                let (_, first, second) = ast.as_binary().expect("every other node is binary");
+                let (first_needs, second_needs) = operand_needs(ast);
+                let (open, separator, close) = match ast {
+                    MathAST::Divide(..) => ("\\frac{", "}{", "}"),
+                    MathAST::FloorDivide(..) => {
+                        ("\\left\\lfloor \\frac{", "}{", "}\\right\\rfloor")
+                    }
+                    MathAST::Power(..) => ("", "^{", "}"),
+                    MathAST::Add(..) => ("", " + ", ""),
+                    MathAST::Subtract(..) => ("", " - ", ""),
+                    MathAST::Multiply(..) => ("", " \\cdot ", ""),
+                    _ => ("", " \\bmod ", ""),
+                };
+                let inline = open.is_empty() && !matches!(ast, MathAST::Power(..));
+
+                write!(f, "{}", open)?;
+                Self::operand(f, first, first_needs, false)?;
+                write!(f, "{}", separator)?;
+                Self::operand(f, second, second_needs, inline)?;
+                write!(f, "{}", close)
+            }
+        }
+    }
+}
+
+/// Presentation MathML `<math>` element - see MathAST::mathml
+pub struct MathML<'a>(&'a MathAST);
+
+/// The elements inside `<math>` for a tree
+struct Elements<'a>(&'a MathAST);
+
+impl Elements<'_> {
+    fn operand(
+        f: &mut fmt::Formatter<'_>,
+        operand: &MathAST,
+        needs: Precedence,
+        after_operator: bool,
+    ) -> fmt::Result {
+        if wrap(operand, needs, after_operator) {
+            write!(f, "<mrow><mo>(</mo>{}<mo>)</mo></mrow>", Elements(operand))
+        } else {
+            write!(f, "{}", Elements(operand))
+        }
+    }
+
+    fn number(f: &mut fmt::Formatter<'_>, v: &Number) -> fmt::Result {
+        if v.is_negative() {
+            write!(f, "<mrow><mo>\u{2212}</mo>")?;
+            Self::number(f, &-v.clone())?;
+            return write!(f, "</mrow>");
+        }
+        match v {
+            Number::Rational(v) if !v.is_integer() => write!(
+                f,
+                "<mfrac><mn>{}</mn><mn>{}</mn></mfrac>",
+                v.numer(),
+                v.denom()
+            ),
+            Number::F64(v) if v.is_nan() => write!(f, "<mi>NaN</mi>"),
+            Number::F64(v) if v.is_infinite() => write!(f, "<mi>\u{221E}</mi>"),
+            v => write!(f, "<mn>{}</mn>", v),
+        }
+    }
+
+    fn call(f: &mut fmt::Formatter<'_>, name: &str, args: &[MathAST]) -> fmt::Result {
+        let delimited = |f: &mut fmt::Formatter<'_>, open: &str, close: &str| {
+            write!(f, "<mrow><mo>{}</mo>", open)?;
+            for (i, arg) in args.iter().enumerate() {
+                if i > 0 {
+                    write!(f, "<mo>,</mo>")?;
+                }
+                write!(f, "{}", Elements(arg))?;
+            }
+            write!(f, "<mo>{}</mo></mrow>", close)
+        };
+
+        match name {
+            "sqrt" => {
+                write!(f, "<msqrt>")?;
+                for arg in args {
+                    write!(f, "{}", Elements(arg))?;
+                }
+                write!(f, "</msqrt>")
+            }
+            "abs" => delimited(f, "|", "|"),
+            "floor" => delimited(f, "\u{230A}", "\u{230B}"),
+            "ceil" => delimited(f, "\u{2308}", "\u{2309}"),
+            // Function application is an invisible operator between the name and its arguments
+            _ => {
+                write!(f, "<mrow><mi>{}</mi><mo>\u{2061}</mo>", name)?;
+                delimited(f, "(", ")")?;
+                write!(f, "</mrow>")
+            }
+        }
+    }
+}
+
+impl fmt::Display for Elements<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            MathAST::Value(v) => Self::number(f, v),
+            MathAST::Var(name) => write!(f, "<mi>{}</mi>", name),
+            MathAST::Call { name, args } => Self::call(f, name, args),
+            MathAST::Negate(v) => {
+                write!(f, "<mrow><mo>\u{2212}</mo>")?;
+                Self::operand(f, v, Precedence::Prefix, true)?;
+                write!(f, "</mrow>")
+            }
+            MathAST::UnaryPlus(v) => {
+                write!(f, "<mrow><mo>+</mo>")?;
+                Self::operand(f, v, Precedence::Prefix, true)?;
+                write!(f, "</mrow>")
+            }
+            ast => {
+                let (_, first, second) = ast.as_binary().expect("every other node is binary");
+                let (first_needs, second_needs) = operand_needs(ast);
+                let (open, separator, close) = match ast {
+                    MathAST::Divide(..) => ("<mfrac>", "", "</mfrac>"),
+                    MathAST::FloorDivide(..) => (
+                        "<mrow><mo>\u{230A}</mo><mfrac>",
+                        "",
+                        "</mfrac><mo>\u{230B}</mo></mrow>",
+                    ),
+                    MathAST::Power(..) => ("<msup>", "", "</msup>"),
+                    MathAST::Add(..) => ("<mrow>", "<mo>+</mo>", "</mrow>"),
+                    MathAST::Subtract(..) => ("<mrow>", "<mo>\u{2212}</mo>", "</mrow>"),
+                    MathAST::Multiply(..) => ("<mrow>", "<mo>\u{22C5}</mo>", "</mrow>"),
+                    _ => ("<mrow>", "<mo>mod</mo>", "</mrow>"),
+                };
+                let inline = !separator.is_empty();
+
+                write!(f, "{}", open)?;
+                Self::operand(f, first, first_needs, false)?;
+                write!(f, "{}", separator)?;
+                Self::operand(f, second, second_needs, inline)?;
+                write!(f, "{}", close)
+            }
+        }
+    }
+}
+
+const MATHML_NAMESPACE: &str = "http://www.w3.org/1998/Math/MathML";
+
+impl fmt::Display for MathML<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "<math xmlns=\"{}\">{}</math>",
+            MATHML_NAMESPACE,
+            Elements(self.0)
+        )
+    }
+}
+
+impl MathAST {
+    /// LaTeX for math mode - divisions are `\frac`, products `\cdot` and powers superscripts
+    pub fn latex(&self) -> Latex<'_> {
+        Latex(self)
+    }
+
+    /// Presentation MathML, a complete `<math>` element ready to embed in HTML
+    pub fn mathml(&self) -> MathML<'_> {
+        MathML(self)
+    }
+}
+
+impl Equation {
+    /// Both sides as LaTeX for math mode
+    pub fn latex(&self) -> String {
+        format!("{} = {}", self.lhs.latex(), self.rhs.latex())
+    }
+
+    /// Both sides in one Presentation MathML `<math>` element
+    pub fn mathml(&self) -> String {
+        format!(
+            "<math xmlns=\"{}\"><mrow>{}<mo>=</mo>{}</mrow></math>",
+            MATHML_NAMESPACE,
+            Elements(&self.lhs),
+            Elements(&self.rhs)
+        )
+    }
+}
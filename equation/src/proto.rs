@@ -1,3 +1,32 @@
 pub mod equation {
     tonic::include_proto!("equation"); // The string specified here must match the proto package name
+
+    impl Operator {
+        /// Infix symbol used in logs and error messages
+        pub fn symbol(&self) -> &'static str {
+            match self {
+                Operator::Add => "+",
+                Operator::Subtract => "-",
+                Operator::Multiply => "*",
+                Operator::Divide => "/",
+                Operator::Power => "^",
+                Operator::Remainder => "%",
+                Operator::FloorDivide => "//",
+                Operator::Unspecified => "?",
+            }
+        }
+
+        /// Name of the service that owns the operator - as its LocalOperator::NAME
+        pub fn service(&self) -> &'static str {
+            match self {
+                Operator::Add => "Adder",
+                Operator::Subtract => "Subtractor",
+                Operator::Multiply => "Multiplier",
+                Operator::Divide => "Divider",
+                Operator::Power => "Power",
+                Operator::Remainder | Operator::FloorDivide => "Modulo",
+                Operator::Unspecified => "?",
+            }
+        }
+    }
 }
@@ -1,21 +1,117 @@
 use tokio::{signal, sync::mpsc::Sender};
 use tonic::Status;
 
+use crate::{
+    number::Number,
+    proto::equation::{NumericDomain, Operator},
+    solve::Unsolvable,
+};
+
+/// Large payloads are boxed so the error stays cheap to return on every path
 #[derive(Debug)]
 pub enum Error {
-    SerdeJSON(serde_json::Error),
-    ExternalServiceStatus(Status),
+    ExternalServiceStatus(Box<Status>),
     NoClientConnectionEstablished,
+    DivisionByZero,
+    Overflow {
+        op: Operator,
+        lhs: Box<Number>,
+        rhs: Box<Number>,
+    },
+    OutOfDomain {
+        value: Box<Number>,
+        domain: NumericDomain,
+    },
+    UnsupportedExponent {
+        exponent: Box<Number>,
+        domain: NumericDomain,
+    },
+    /// A function call that can't be made - unknown name, wrong arity or bad arguments
+    InvalidCall {
+        name: String,
+        reason: String,
+    },
+    /// Variables with no value in the request's bindings - sorted and without duplicates
+    UnboundVariables(Vec<String>),
+    /// An equation with no single solution for its unknown
+    Unsolvable(Unsolvable),
+    /// An expression with no derivative with respect to `var`
+    NotDifferentiable {
+        var: String,
+        reason: String,
+    },
+}
+
+impl Error {
+    pub fn overflow(op: Operator, lhs: Number, rhs: Number) -> Self {
+        Error::Overflow {
+            op,
+            lhs: Box::new(lhs),
+            rhs: Box::new(rhs),
+        }
+    }
+
+    pub fn out_of_domain(value: Number, domain: NumericDomain) -> Self {
+        Error::OutOfDomain {
+            value: Box::new(value),
+            domain,
+        }
+    }
+}
+
+impl From<Status> for Error {
+    fn from(value: Status) -> Self {
+        Error::ExternalServiceStatus(Box::new(value))
+    }
 }
 
 impl From<Error> for Status {
     fn from(value: Error) -> Self {
-        Status::internal(format!("Adder Service Error: {:#?}", value))
+        match value {
+            // Pass statuses from services we delegated to back up the chain untouched so the
+            // orchestrator sees the original error no matter how many hops it went through
+            Error::ExternalServiceStatus(status) => *status,
+            Error::DivisionByZero => Status::invalid_argument("Division by zero"),
+            Error::Overflow { op, lhs, rhs } => Status::out_of_range(format!(
+                "Overflow evaluating {} {} {}",
+                lhs,
+                op.symbol(),
+                rhs
+            )),
+            Error::OutOfDomain { value, domain } => Status::out_of_range(format!(
+                "{} can't be represented as {}",
+                value,
+                domain.as_str_name()
+            )),
+            Error::UnsupportedExponent { exponent, domain } => Status::invalid_argument(format!(
+                "{} isn't a supported exponent in {}",
+                exponent,
+                domain.as_str_name()
+            )),
+            Error::InvalidCall { name, reason } => {
+                Status::invalid_argument(format!("{}(): {}", name, reason))
+            }
+            Error::UnboundVariables(names) => Status::invalid_argument(format!(
+                "Unbound variable{}: {}",
+                if names.len() == 1 { "" } else { "s" },
+                names.join(", ")
+            )),
+            Error::Unsolvable(reason) => {
+                Status::invalid_argument(format!("Can't solve: {}", reason))
+            }
+            Error::NotDifferentiable { var, reason } => Status::invalid_argument(format!(
+                "Can't differentiate with respect to {}: {}",
+                var, reason
+            )),
+            Error::NoClientConnectionEstablished => {
+                Status::internal(format!("Equation Service Error: {:#?}", value))
+            }
+        }
     }
 }
 
 pub async fn wait_for_ctrl_c(tx: Sender<()>) {
     let _ = signal::ctrl_c().await;
     println!("SIGTERM received: shutting down");
-    let _ = tx.send(());
+    let _ = tx.send(()).await;
 }
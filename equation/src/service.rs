@@ -0,0 +1,577 @@
+use std::{fmt::Debug, marker::PhantomData, sync::Arc};
+
+use async_trait::async_trait;
+use tokio::sync::{mpsc, mpsc::Sender, Mutex, Semaphore};
+use tonic::{
+    transport::{server::Router, Channel, Server},
+    Request, Response, Status,
+};
+
+use crate::{
+    client::{Connect, LazyClient},
+    config::Config,
+    differentiate::differentiate,
+    function,
+    number::Number,
+    parse::{Context, MathAST, MathASTEvaluator},
+    proto::equation::{
+        adder_client::AdderClient,
+        adder_server::{Adder, AdderServer},
+        divider_client::DividerClient,
+        divider_server::{Divider, DividerServer},
+        functions_client::FunctionsClient,
+        functions_server::{self, FunctionsServer},
+        modulo_client::ModuloClient,
+        modulo_server::{self, ModuloServer},
+        multiplier_client::MultiplierClient,
+        multiplier_server::{Multiplier, MultiplierServer},
+        power_client::PowerClient,
+        power_server::{self, PowerServer},
+        solver_server::{self, SolverServer},
+        subtractor_client::SubtractorClient,
+        subtractor_server::{Subtractor, SubtractorServer},
+        CalculationRequest, CalculationResponse, CallRequest, DerivativeRequest,
+        DerivativeResponse, Empty, Operator, SystemRequest, SystemResponse,
+    },
+    server::Error,
+    solve::solve_system,
+};
+
+/// The operators a service evaluates locally - every other operation is delegated to its service
+pub trait LocalOperator: Send + Sync + Sized + 'static {
+    const OPERATORS: &'static [Operator];
+    /// Service name used in logs
+    const NAME: &'static str;
+    /// Whether built in function calls are evaluated here rather than by the Functions service
+    const EVALUATES_FUNCTIONS: bool = false;
+
+    /// Address this service listens on
+    fn addr(config: &Config) -> &str;
+
+    /// Register the tonic server for our operators
+    fn router(builder: &mut Server, service: OperatorService<Self>) -> Router;
+
+    fn owns(op: Operator) -> bool {
+        Self::OPERATORS.contains(&op)
+    }
+}
+
+pub struct Add;
+pub struct Subtract;
+pub struct Multiply;
+pub struct Divide;
+pub struct Power;
+pub struct Modulo;
+pub struct Functions;
+/// Owns no operators - every numeric subexpression of a system is delegated
+pub struct Solver;
+
+impl LocalOperator for Add {
+    const OPERATORS: &'static [Operator] = &[Operator::Add];
+    const NAME: &'static str = "Adder";
+
+    fn addr(config: &Config) -> &str {
+        &config.adder_addr
+    }
+
+    fn router(builder: &mut Server, service: OperatorService<Self>) -> Router {
+        builder.add_service(AdderServer::new(service))
+    }
+}
+
+impl LocalOperator for Subtract {
+    const OPERATORS: &'static [Operator] = &[Operator::Subtract];
+    const NAME: &'static str = "Subtractor";
+
+    fn addr(config: &Config) -> &str {
+        &config.subtractor_addr
+    }
+
+    fn router(builder: &mut Server, service: OperatorService<Self>) -> Router {
+        builder.add_service(SubtractorServer::new(service))
+    }
+}
+
+impl LocalOperator for Multiply {
+    const OPERATORS: &'static [Operator] = &[Operator::Multiply];
+    const NAME: &'static str = "Multiplier";
+
+    fn addr(config: &Config) -> &str {
+        &config.multiplier_addr
+    }
+
+    fn router(builder: &mut Server, service: OperatorService<Self>) -> Router {
+        builder.add_service(MultiplierServer::new(service))
+    }
+}
+
+impl LocalOperator for Divide {
+    const OPERATORS: &'static [Operator] = &[Operator::Divide];
+    const NAME: &'static str = "Divider";
+
+    fn addr(config: &Config) -> &str {
+        &config.divider_addr
+    }
+
+    fn router(builder: &mut Server, service: OperatorService<Self>) -> Router {
+        builder.add_service(DividerServer::new(service))
+    }
+}
+
+impl LocalOperator for Power {
+    const OPERATORS: &'static [Operator] = &[Operator::Power];
+    const NAME: &'static str = "Power";
+
+    fn addr(config: &Config) -> &str {
+        &config.power_addr
+    }
+
+    fn router(builder: &mut Server, service: OperatorService<Self>) -> Router {
+        builder.add_service(PowerServer::new(service))
+    }
+}
+
+impl LocalOperator for Modulo {
+    const OPERATORS: &'static [Operator] = &[Operator::Remainder, Operator::FloorDivide];
+    const NAME: &'static str = "Modulo";
+
+    fn addr(config: &Config) -> &str {
+        &config.modulo_addr
+    }
+
+    fn router(builder: &mut Server, service: OperatorService<Self>) -> Router {
+        builder.add_service(ModuloServer::new(service))
+    }
+}
+
+impl LocalOperator for Functions {
+    const OPERATORS: &'static [Operator] = &[];
+    const NAME: &'static str = "Functions";
+    const EVALUATES_FUNCTIONS: bool = true;
+
+    fn addr(config: &Config) -> &str {
+        &config.functions_addr
+    }
+
+    fn router(builder: &mut Server, service: OperatorService<Self>) -> Router {
+        builder.add_service(FunctionsServer::new(service))
+    }
+}
+
+impl LocalOperator for Solver {
+    const OPERATORS: &'static [Operator] = &[];
+    const NAME: &'static str = "Solver";
+
+    fn addr(config: &Config) -> &str {
+        &config.solver_addr
+    }
+
+    fn router(builder: &mut Server, service: OperatorService<Self>) -> Router {
+        builder.add_service(SolverServer::new(service))
+    }
+}
+
+/// Micro service that evaluates the operators of `O` itself and delegates all other operations to their services
+#[derive(Debug)]
+pub struct OperatorService<O> {
+    config: Config,
+    term_channel: Arc<Mutex<Sender<()>>>,
+    /// Caps the delegated calls in flight - eval() fans out across every independent subtree
+    remote_permits: Semaphore,
+    add_client: LazyClient<AdderClient<Channel>>,
+    subtract_client: LazyClient<SubtractorClient<Channel>>,
+    multiply_client: LazyClient<MultiplierClient<Channel>>,
+    divide_client: LazyClient<DividerClient<Channel>>,
+    power_client: LazyClient<PowerClient<Channel>>,
+    modulo_client: LazyClient<ModuloClient<Channel>>,
+    functions_client: LazyClient<FunctionsClient<Channel>>,
+    operator: PhantomData<O>,
+}
+
+impl<O: LocalOperator> OperatorService<O> {
+    /// Create new service - get whatever external service connections we can on boot
+    /// The others are initialized at request time, we never need a connection to ourselves
+    pub async fn new(config: &Config, term_channel: Sender<()>) -> Self {
+        Self {
+            config: config.clone(),
+            term_channel: Arc::new(Mutex::new(term_channel)),
+            remote_permits: Semaphore::new(config.max_concurrency),
+            add_client: remote_client(config, !O::owns(Operator::Add)).await,
+            subtract_client: remote_client(config, !O::owns(Operator::Subtract)).await,
+            multiply_client: remote_client(config, !O::owns(Operator::Multiply)).await,
+            divide_client: remote_client(config, !O::owns(Operator::Divide)).await,
+            power_client: remote_client(config, !O::owns(Operator::Power)).await,
+            modulo_client: remote_client(
+                config,
+                !O::owns(Operator::Remainder) && !O::owns(Operator::FloorDivide),
+            )
+            .await,
+            functions_client: remote_client(config, !O::EVALUATES_FUNCTIONS).await,
+            operator: PhantomData,
+        }
+    }
+
+    /// Apply `op` locally if it's ours otherwise delegate to the service that owns it
+    async fn apply(
+        &self,
+        ctx: &Context,
+        op: Operator,
+        first: Number,
+        second: Number,
+    ) -> Result<Number, Error> {
+        if O::owns(op) {
+            return Number::apply(op, ctx, first, second);
+        }
+
+        let message = CalculationRequest::new(MathAST::Value(first), MathAST::Value(second), ctx);
+        let _permit = self
+            .remote_permits
+            .acquire()
+            .await
+            .expect("Semaphore is never closed");
+
+        let res = match op {
+            Operator::Add => self.add_client.get(&self.config).await?.add(message).await,
+            Operator::Subtract => {
+                self.subtract_client
+                    .get(&self.config)
+                    .await?
+                    .subtract(message)
+                    .await
+            }
+            Operator::Multiply => {
+                self.multiply_client
+                    .get(&self.config)
+                    .await?
+                    .multiply(message)
+                    .await
+            }
+            Operator::Divide => {
+                self.divide_client
+                    .get(&self.config)
+                    .await?
+                    .divide(message)
+                    .await
+            }
+            Operator::Power => {
+                self.power_client
+                    .get(&self.config)
+                    .await?
+                    .power(message)
+                    .await
+            }
+            Operator::Remainder => {
+                self.modulo_client
+                    .get(&self.config)
+                    .await?
+                    .remainder(message)
+                    .await
+            }
+            Operator::FloorDivide => {
+                self.modulo_client
+                    .get(&self.config)
+                    .await?
+                    .floor_divide(message)
+                    .await
+            }
+            Operator::Unspecified => unreachable!("only concrete operators are evaluated"),
+        };
+
+        Ok(res?.into_inner().into_result()?)
+    }
+
+    /// Apply the built in function locally if we're the Functions service otherwise delegate to it
+    async fn apply_function(
+        &self,
+        ctx: &Context,
+        name: &str,
+        args: Vec<Number>,
+    ) -> Result<Number, Error> {
+        if O::EVALUATES_FUNCTIONS {
+            return function::call(ctx, name, args);
+        }
+
+        let message = CallRequest::new(name, args.into_iter().map(MathAST::Value).collect(), ctx);
+        let _permit = self
+            .remote_permits
+            .acquire()
+            .await
+            .expect("Semaphore is never closed");
+
+        let res = self
+            .functions_client
+            .get(&self.config)
+            .await?
+            .call(message)
+            .await?;
+        Ok(res.into_inner().into_result()?)
+    }
+
+    /// Evaluate the root operation of a request
+    async fn calculate(
+        &self,
+        request: Request<CalculationRequest>,
+        root: fn(Box<MathAST>, Box<MathAST>) -> MathAST,
+    ) -> Result<Response<CalculationResponse>, Status> {
+        let request = request.into_inner();
+        let trace = request.trace;
+        let (first, second, ctx) = request.into_args()?;
+        let ast = root(Box::new(first), Box::new(second));
+
+        self.respond(&ctx, ast, trace).await
+    }
+
+    /// Evaluate `ast`, recording the steps taken when the caller asked for them
+    async fn respond(
+        &self,
+        ctx: &Context,
+        ast: MathAST,
+        trace: bool,
+    ) -> Result<Response<CalculationResponse>, Status> {
+        let response = if trace {
+            let (result, step) = self.trace(ctx, ast).await?;
+            CalculationResponse::new(result).with_trace(step)
+        } else {
+            CalculationResponse::new(self.eval(ctx, ast).await?)
+        };
+
+        Ok(Response::new(response))
+    }
+
+    async fn terminate(&self) -> Result<Response<Empty>, Status> {
+        let channel = self.term_channel.lock().await;
+        let _ = channel.send(()).await;
+
+        Ok(Response::new(Empty {}))
+    }
+}
+
+/// Connect eagerly to the services we delegate to
+async fn remote_client<C: Connect + Clone + Debug>(
+    config: &Config,
+    connect: bool,
+) -> LazyClient<C> {
+    if connect {
+        LazyClient::new(config).await
+    } else {
+        LazyClient::empty()
+    }
+}
+
+#[async_trait]
+impl<O: LocalOperator> MathASTEvaluator<Error> for OperatorService<O> {
+    async fn add(&self, ctx: &Context, first: Number, second: Number) -> Result<Number, Error> {
+        self.apply(ctx, Operator::Add, first, second).await
+    }
+    async fn subtract(
+        &self,
+        ctx: &Context,
+        first: Number,
+        second: Number,
+    ) -> Result<Number, Error> {
+        self.apply(ctx, Operator::Subtract, first, second).await
+    }
+    async fn multiply(
+        &self,
+        ctx: &Context,
+        first: Number,
+        second: Number,
+    ) -> Result<Number, Error> {
+        self.apply(ctx, Operator::Multiply, first, second).await
+    }
+    async fn divide(&self, ctx: &Context, first: Number, second: Number) -> Result<Number, Error> {
+        self.apply(ctx, Operator::Divide, first, second).await
+    }
+    async fn power(&self, ctx: &Context, first: Number, second: Number) -> Result<Number, Error> {
+        self.apply(ctx, Operator::Power, first, second).await
+    }
+    async fn remainder(
+        &self,
+        ctx: &Context,
+        first: Number,
+        second: Number,
+    ) -> Result<Number, Error> {
+        self.apply(ctx, Operator::Remainder, first, second).await
+    }
+    async fn floor_divide(
+        &self,
+        ctx: &Context,
+        first: Number,
+        second: Number,
+    ) -> Result<Number, Error> {
+        self.apply(ctx, Operator::FloorDivide, first, second).await
+    }
+    async fn call(&self, ctx: &Context, name: &str, args: Vec<Number>) -> Result<Number, Error> {
+        self.apply_function(ctx, name, args).await
+    }
+    fn name(&self) -> &str {
+        O::NAME
+    }
+}
+
+#[tonic::async_trait]
+impl<O: LocalOperator> Adder for OperatorService<O> {
+    async fn add(
+        &self,
+        request: Request<CalculationRequest>,
+    ) -> Result<Response<CalculationResponse>, Status> {
+        self.calculate(request, MathAST::Add).await
+    }
+
+    async fn term(&self, _: Request<Empty>) -> Result<Response<Empty>, Status> {
+        self.terminate().await
+    }
+}
+
+#[tonic::async_trait]
+impl<O: LocalOperator> Subtractor for OperatorService<O> {
+    async fn subtract(
+        &self,
+        request: Request<CalculationRequest>,
+    ) -> Result<Response<CalculationResponse>, Status> {
+        self.calculate(request, MathAST::Subtract).await
+    }
+
+    async fn term(&self, _: Request<Empty>) -> Result<Response<Empty>, Status> {
+        self.terminate().await
+    }
+}
+
+#[tonic::async_trait]
+impl<O: LocalOperator> Multiplier for OperatorService<O> {
+    async fn multiply(
+        &self,
+        request: Request<CalculationRequest>,
+    ) -> Result<Response<CalculationResponse>, Status> {
+        self.calculate(request, MathAST::Multiply).await
+    }
+
+    async fn term(&self, _: Request<Empty>) -> Result<Response<Empty>, Status> {
+        self.terminate().await
+    }
+}
+
+#[tonic::async_trait]
+impl<O: LocalOperator> Divider for OperatorService<O> {
+    async fn divide(
+        &self,
+        request: Request<CalculationRequest>,
+    ) -> Result<Response<CalculationResponse>, Status> {
+        self.calculate(request, MathAST::Divide).await
+    }
+
+    async fn term(&self, _: Request<Empty>) -> Result<Response<Empty>, Status> {
+        self.terminate().await
+    }
+}
+
+#[tonic::async_trait]
+impl<O: LocalOperator> power_server::Power for OperatorService<O> {
+    async fn power(
+        &self,
+        request: Request<CalculationRequest>,
+    ) -> Result<Response<CalculationResponse>, Status> {
+        self.calculate(request, MathAST::Power).await
+    }
+
+    async fn term(&self, _: Request<Empty>) -> Result<Response<Empty>, Status> {
+        self.terminate().await
+    }
+}
+
+#[tonic::async_trait]
+impl<O: LocalOperator> modulo_server::Modulo for OperatorService<O> {
+    async fn remainder(
+        &self,
+        request: Request<CalculationRequest>,
+    ) -> Result<Response<CalculationResponse>, Status> {
+        self.calculate(request, MathAST::Remainder).await
+    }
+
+    async fn floor_divide(
+        &self,
+        request: Request<CalculationRequest>,
+    ) -> Result<Response<CalculationResponse>, Status> {
+        self.calculate(request, MathAST::FloorDivide).await
+    }
+
+    async fn term(&self, _: Request<Empty>) -> Result<Response<Empty>, Status> {
+        self.terminate().await
+    }
+}
+
+#[tonic::async_trait]
+impl<O: LocalOperator> functions_server::Functions for OperatorService<O> {
+    async fn call(
+        &self,
+        request: Request<CallRequest>,
+    ) -> Result<Response<CalculationResponse>, Status> {
+        let request = request.into_inner();
+        let trace = request.trace;
+        let (call, ctx) = request.into_args()?;
+
+        self.respond(&ctx, call, trace).await
+    }
+
+    async fn term(&self, _: Request<Empty>) -> Result<Response<Empty>, Status> {
+        self.terminate().await
+    }
+}
+
+#[tonic::async_trait]
+impl<O: LocalOperator> solver_server::Solver for OperatorService<O> {
+    async fn solve_system(
+        &self,
+        request: Request<SystemRequest>,
+    ) -> Result<Response<SystemResponse>, Status> {
+        let (equations, ctx) = request.into_inner().into_args()?;
+        let solution = solve_system(self, &ctx, equations).await?;
+
+        Ok(Response::new(SystemResponse::new(solution)))
+    }
+
+    async fn differentiate(
+        &self,
+        request: Request<DerivativeRequest>,
+    ) -> Result<Response<DerivativeResponse>, Status> {
+        let (ast, var, ctx) = request.into_inner().into_args()?;
+        let derivative = differentiate(&ast, &var, &ctx)?;
+
+        Ok(Response::new(DerivativeResponse::new(derivative)))
+    }
+
+    async fn term(&self, _: Request<Empty>) -> Result<Response<Empty>, Status> {
+        self.terminate().await
+    }
+}
+
+/// Stack for the threads requests are evaluated on - evaluating and tracing recurse once per level of
+/// the tree, a debug build takes about half of this for the deepest tree a parser accepts
+pub(crate) const WORKER_STACK_SIZE: usize = 8 * 1024 * 1024;
+
+/// Run the service for `O` until a Term request is received
+pub fn serve<O: LocalOperator>() -> Result<(), Box<dyn std::error::Error>> {
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .thread_stack_size(WORKER_STACK_SIZE)
+        .build()?
+        .block_on(run::<O>())
+}
+
+async fn run<O: LocalOperator>() -> Result<(), Box<dyn std::error::Error>> {
+    println!("{} Init!", O::NAME);
+
+    let (tx, mut rx) = mpsc::channel(100);
+    let config = Config::new();
+    let service = OperatorService::<O>::new(&config, tx).await;
+
+    O::router(&mut Server::builder(), service)
+        .serve_with_shutdown(O::addr(&config).parse()?, async {
+            rx.recv().await;
+            println!("Master shutdown request received by {}", O::NAME);
+        })
+        .await?;
+
+    println!("{} Shutdown!", O::NAME);
+    Ok(())
+}
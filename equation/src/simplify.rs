@@ -0,0 +1,375 @@
+use num_rational::BigRational;
+use num_traits::One;
+
+use crate::{
+    function,
+    number::Number,
+    parse::{Context, MathAST},
+    proto::equation::{NumericDomain, Operator},
+};
+
+/// Rewrite a tree into an equivalent smaller one, bottom up:
+/// - literal subtrees are folded to their value in the domain of `ctx`
+/// - `x + 0`, `x - 0`, `x * 1`, `x / 1`, `x ^ 1` become `x`, `0 - x` becomes `-x` and `x * 0` becomes `0`
+/// - `--x` and `+x` become `x`
+/// - like terms of sums are collected, `2 * x + 3 - x + 1` becomes `x + 4`
+///
+/// Anything that fails to fold (division by zero, overflow...) is left in place so evaluating the
+/// result reports the same error - `* 0` and cancelling terms only drop variables and literals
+/// Floats keep `x * 0` (NaN and infinities) and floats and decimals keep their sums as written
+/// since reassociating them changes the rounding
+pub fn simplify(ast: MathAST, ctx: &Context) -> MathAST {
+    match ast {
+        MathAST::Value(_) | MathAST::Var(_) => ast,
+        MathAST::Negate(v) => negate(simplify(*v, ctx), ctx),
+        MathAST::UnaryPlus(v) => simplify(*v, ctx),
+        MathAST::Call { name, args } => {
+            let args: Vec<_> = args.into_iter().map(|arg| simplify(arg, ctx)).collect();
+            let values: Option<Vec<_>> = args
+                .iter()
+                .map(|arg| match arg {
+                    MathAST::Value(v) => Some(v.clone()),
+                    _ => None,
+                })
+                .collect();
+
+            match values.map(|values| function::call(ctx, &name, values)) {
+                Some(Ok(res)) => MathAST::Value(res),
+                _ => MathAST::Call { name, args },
+            }
+        }
+        MathAST::Add(f, s) => binary(Operator::Add, *f, *s, ctx),
+        MathAST::Subtract(f, s) => binary(Operator::Subtract, *f, *s, ctx),
+        MathAST::Multiply(f, s) => binary(Operator::Multiply, *f, *s, ctx),
+        MathAST::Divide(f, s) => binary(Operator::Divide, *f, *s, ctx),
+        MathAST::Power(f, s) => binary(Operator::Power, *f, *s, ctx),
+        MathAST::Remainder(f, s) => binary(Operator::Remainder, *f, *s, ctx),
+        MathAST::FloorDivide(f, s) => binary(Operator::FloorDivide, *f, *s, ctx),
+    }
+}
+
+fn negate(v: MathAST, ctx: &Context) -> MathAST {
+    match v {
+        MathAST::Negate(inner) => *inner,
+        MathAST::Value(n) => match n.clone().coerce(ctx).and_then(|n| (-n).coerce(ctx)) {
+            Ok(res) => MathAST::Value(res),
+            Err(_) => MathAST::Negate(Box::new(MathAST::Value(n))),
+        },
+        v => MathAST::Negate(Box::new(v)),
+    }
+}
+
+fn binary(op: Operator, first: MathAST, second: MathAST, ctx: &Context) -> MathAST {
+    let (first, second) = (simplify(first, ctx), simplify(second, ctx));
+
+    if let (MathAST::Value(f), MathAST::Value(s)) = (&first, &second) {
+        if let Ok(res) = Number::apply(op, ctx, f.clone(), s.clone()) {
+            return MathAST::Value(res);
+        }
+    }
+
+    let float = ctx.domain == NumericDomain::Float64;
+    match (op, first, second) {
+        (Operator::Add, v, zero) | (Operator::Add, zero, v) if is(&zero, 0) => v,
+        (Operator::Subtract, v, zero) if is(&zero, 0) => v,
+        (Operator::Subtract, zero, v) if is(&zero, 0) => negate(v, ctx),
+        (Operator::Multiply, v, one) | (Operator::Multiply, one, v) if is(&one, 1) => v,
+        (Operator::Multiply, v, zero) | (Operator::Multiply, zero, v)
+            if is(&zero, 0) && infallible(&v) && !float =>
+        {
+            match Number::from(0).coerce(ctx) {
+                Ok(zero) => MathAST::Value(zero),
+                Err(_) => zero,
+            }
+        }
+        (Operator::Divide, v, one) if is(&one, 1) => v,
+        (Operator::Power, v, one) if is(&one, 1) => v,
+        (op, first, second) => {
+            let ast = rebuild(op, first, second);
+            match op {
+                Operator::Add | Operator::Subtract if exact(ctx) => collect_terms(ast, ctx),
+                _ => ast,
+            }
+        }
+    }
+}
+
+/// Whether `ast` is the literal `n`
+fn is(ast: &MathAST, n: i32) -> bool {
+    match ast {
+        MathAST::Value(v) => v.to_rational() == Some(BigRational::from_integer(n.into())),
+        _ => false,
+    }
+}
+
+/// Whether `ast` is safe to drop - a literal or a term over variables with no operation left on
+/// literals alone, since a simplified tree only keeps those when they failed to fold
+fn infallible(ast: &MathAST) -> bool {
+    let operands: Vec<&MathAST> = match ast {
+        MathAST::Value(_) | MathAST::Var(_) => return true,
+        MathAST::Negate(v) | MathAST::UnaryPlus(v) => vec![v],
+        MathAST::Call { args, .. } => args.iter().collect(),
+        ast => {
+            let (_, first, second) = ast.as_binary().expect("every other node is binary");
+            vec![first, second]
+        }
+    };
+    !ast.variables().is_empty() && operands.into_iter().all(infallible)
+}
+
+/// Domains where sums can be reordered without changing the result
+fn exact(ctx: &Context) -> bool {
+    !matches!(ctx.domain, NumericDomain::Float64 | NumericDomain::Decimal)
+}
+
+fn rebuild(op: Operator, first: MathAST, second: MathAST) -> MathAST {
+    let (first, second) = (Box::new(first), Box::new(second));
+
+    match op {
+        Operator::Add => MathAST::Add(first, second),
+        Operator::Subtract => MathAST::Subtract(first, second),
+        Operator::Multiply => MathAST::Multiply(first, second),
+        Operator::Divide => MathAST::Divide(first, second),
+        Operator::Power => MathAST::Power(first, second),
+        Operator::Remainder => MathAST::Remainder(first, second),
+        Operator::FloorDivide => MathAST::FloorDivide(first, second),
+        Operator::Unspecified => unreachable!("only concrete operators are parsed"),
+    }
+}
+
+/// A sum flattened into `coefficient * term` pieces - constants have no term
+struct Term {
+    coefficient: Number,
+    term: Option<MathAST>,
+}
+
+fn flatten(ast: MathAST, negative: bool, terms: &mut Vec<Term>) {
+    let signed = |coefficient: Number| if negative { -coefficient } else { coefficient };
+
+    // Anything over literals alone but a literal failed to fold, it stays whole so its constants
+    // aren't summed into the others and evaluating it still reports the failure
+    if !matches!(ast, MathAST::Value(_)) && ast.variables().is_empty() {
+        terms.push(Term {
+            coefficient: signed(Number::from(1)),
+            term: Some(ast),
+        });
+        return;
+    }
+
+    match ast {
+        MathAST::Add(f, s) => {
+            flatten(*f, negative, terms);
+            flatten(*s, negative, terms);
+        }
+        MathAST::Subtract(f, s) => {
+            flatten(*f, negative, terms);
+            flatten(*s, !negative, terms);
+        }
+        MathAST::Negate(v) => flatten(*v, !negative, terms),
+        MathAST::Value(v) => terms.push(Term {
+            coefficient: signed(v),
+            term: None,
+        }),
+        MathAST::Multiply(f, s) => match (*f, *s) {
+            (MathAST::Value(c), term) | (term, MathAST::Value(c)) => terms.push(Term {
+                coefficient: signed(c),
+                term: Some(term),
+            }),
+            (f, s) => terms.push(Term {
+                coefficient: signed(Number::from(1)),
+                term: Some(MathAST::Multiply(Box::new(f), Box::new(s))),
+            }),
+        },
+        term => terms.push(Term {
+            coefficient: signed(Number::from(1)),
+            term: Some(term),
+        }),
+    }
+}
+
+/// Combine the coefficients of equal terms in a sum - left as it is unless that shrinks it
+fn collect_terms(ast: MathAST, ctx: &Context) -> MathAST {
+    let mut terms = Vec::new();
+    flatten(ast.clone(), false, &mut terms);
+    let count = terms.len();
+
+    let mut collected: Vec<Term> = Vec::new();
+    for Term { coefficient, term } in terms {
+        match collected.iter_mut().find(|c| c.term == term) {
+            Some(like) => {
+                match Number::apply(Operator::Add, ctx, like.coefficient.clone(), coefficient) {
+                    Ok(sum) => like.coefficient = sum,
+                    Err(_) => return ast,
+                }
+            }
+            None => collected.push(Term { coefficient, term }),
+        }
+    }
+    if collected.len() == count {
+        return ast;
+    }
+
+    // Constants go last so `1 + x + 2` reads `x + 3`
+    collected.sort_by_key(|t| t.term.is_none());
+    let mut sum: Option<MathAST> = None;
+    for Term { coefficient, term } in collected {
+        if coefficient.is_zero() {
+            // Cancelling a term that may fail would turn its error into an answer
+            match term {
+                Some(term) if !infallible(&term) => return ast,
+                _ => continue,
+            }
+        }
+        let negative = coefficient.is_negative();
+        let magnitude = if negative { -coefficient } else { coefficient };
+        let magnitude = match magnitude.coerce(ctx) {
+            Ok(magnitude) => magnitude,
+            Err(_) => return ast,
+        };
+        let piece = match term {
+            None => MathAST::Value(magnitude),
+            Some(term) if magnitude.to_rational() == Some(BigRational::one()) => term,
+            Some(term) => MathAST::Multiply(Box::new(MathAST::Value(magnitude)), Box::new(term)),
+        };
+
+        sum = Some(match (sum, negative) {
+            (None, false) => piece,
+            (None, true) => MathAST::Negate(Box::new(piece)),
+            (Some(sum), false) => MathAST::Add(Box::new(sum), Box::new(piece)),
+            (Some(sum), true) => MathAST::Subtract(Box::new(sum), Box::new(piece)),
+        });
+    }
+
+    sum.unwrap_or_else(|| match Number::from(0).coerce(ctx) {
+        Ok(zero) => MathAST::Value(zero),
+        Err(_) => ast,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        parse::{parse, tests::TestASTEvaluator, MathASTEvaluator},
+        proto::equation::Rounding,
+    };
+
+    fn simplified(input: &str, ctx: &Context) -> MathAST {
+        simplify(parse(input).unwrap(), ctx)
+    }
+
+    fn assert_simplifies(input: &str, expected: &str) {
+        let ctx = Context::default();
+        assert_eq!(
+            simplified(input, &ctx),
+            parse(expected).unwrap(),
+            "simplifying {}",
+            input
+        );
+    }
+
+    #[test]
+    fn test_simplify_constants() {
+        assert_simplifies("( ( (3 + 3)*2) /4) – 2", "1");
+        assert_simplifies("max(1, 2 ^ 3) - sqrt(16)", "4");
+        assert_simplifies("--x + +(2 * 3)", "x + 6");
+
+        // Failures are left for evaluation to report
+        assert_simplifies("1 + 4 / (2 - 2)", "1 + 4 / 0");
+        assert_simplifies("2147483647 + 1", "2147483647 + 1");
+        assert_simplifies("sqrt(2) * x", "sqrt(2) * x");
+
+        let ctx = Context::new(NumericDomain::Rational);
+        assert_eq!(simplified("7 / 2 * x", &ctx), {
+            let half = Number::from_literal("3.5").coerce(&ctx).unwrap();
+            MathAST::Multiply(
+                Box::new(MathAST::Value(half)),
+                Box::new(MathAST::Var("x".to_string())),
+            )
+        });
+    }
+
+    #[test]
+    fn test_simplify_identities() {
+        assert_simplifies("x * 1 + 0", "x");
+        assert_simplifies("(1 * x) / 1 - 0", "x");
+        assert_simplifies("0 - x", "-x");
+        assert_simplifies("0 - -x", "x");
+        assert_simplifies("(x + y) * (3 - 3)", "0");
+        assert_simplifies("(x + 4 / 0) * 0", "(x + 4 / 0) * 0");
+        assert_simplifies("x ^ (2 - 1) * 0 + y", "y");
+        assert_simplifies("(x + 1) ^ (3 - 2)", "x + 1");
+
+        // NaN * 0 is still NaN
+        let ctx = Context::new(NumericDomain::Float64);
+        assert_eq!(simplified("x * 0", &ctx), parse("x * 0").unwrap());
+    }
+
+    #[test]
+    fn test_simplify_like_terms() {
+        assert_simplifies("2 * x + 3 - x + 1", "x + 4");
+        assert_simplifies("x + x + y * 3 - 2 * y", "2 * x + y");
+        assert_simplifies("x - x", "0");
+        assert_simplifies("1 - 2 * x - x", "-(3 * x) + 1");
+        assert_simplifies("sqrt(x) + 2 * sqrt(x) - y", "3 * sqrt(x) - y");
+        // Nothing to collect leaves the sum as written
+        assert_simplifies("1 + x - y", "1 + x - y");
+
+        // Reassociating decimals would change how they round
+        let ctx = Context::decimal(2, Rounding::HalfEven);
+        assert_eq!(simplified("x + 2 * x", &ctx), parse("x + 2 * x").unwrap());
+    }
+
+    #[actix_rt::test]
+    async fn test_simplify_preserves_errors() {
+        let evaluator = TestASTEvaluator::default();
+        let ctx = Context::default();
+
+        for input in [
+            "1 / 0 - 1 / 0",
+            "5 % 0 - 5 % 0",
+            "sqrt(0 - 1) - sqrt(0 - 1)",
+            "(1 / 0) * 0",
+            "0 * sqrt(0 - 1)",
+            "2147483647 * 2 - 2147483647 * 2",
+            "(x + 1 / 0) * 0",
+            "-1 + (2147483647 + 1)",
+            "1 - 2 + (2147483647 + 1)",
+        ] {
+            let ast = parse(input).unwrap();
+            let expected = evaluator.eval(&ctx, ast.clone()).await.unwrap_err();
+            let res = evaluator.eval(&ctx, simplify(ast, &ctx)).await;
+            assert_eq!(
+                format!("{:?}", res),
+                format!("{:?}", Err::<Number, _>(expected)),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_simplify_preserves_value() {
+        let evaluator = TestASTEvaluator::default();
+
+        for input in [
+            "(1 + 2) * (10 - 4) / 3 - 5",
+            "2 ^ 3 ^ 2 - (1 + 2) ^ 2 * 4",
+            "-(2 * -3) + -(4 - 10) / 3",
+            "max(2, 1 + 2) ^ 2 + sqrt(abs(-16)) % 3",
+            "7 // 2 - 7 / 2 * 1 + 0",
+        ] {
+            for ctx in [
+                Context::default(),
+                Context::new(NumericDomain::Rational),
+                Context::new(NumericDomain::Float64),
+                Context::decimal(3, Rounding::HalfUp),
+            ] {
+                let ast = parse(input).unwrap();
+                let expected = evaluator.eval(&ctx, ast.clone()).await.unwrap();
+                let res = evaluator.eval(&ctx, simplify(ast, &ctx)).await.unwrap();
+                assert_eq!(res, expected, "{} in {:?}", input, ctx.domain);
+            }
+        }
+    }
+}
@@ -0,0 +1,647 @@
+use std::{collections::BTreeMap, fmt};
+
+use futures::{future::BoxFuture, FutureExt};
+use num_rational::BigRational;
+use num_traits::{One, Zero};
+use tokio::try_join;
+
+use crate::{
+    number::Number,
+    parse::{Context, Equation, MathAST, MathASTEvaluator},
+    proto::equation::NumericDomain,
+    server::Error,
+};
+
+/// Why an equation has no single solution
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Unsolvable {
+    /// Every variable was bound so there's nothing to solve for
+    NoUnknown,
+    TooManyUnknowns(Vec<String>),
+    /// The unknown is multiplied by itself, divided by or used in a power or function
+    NonLinear {
+        unknown: String,
+        reason: String,
+    },
+    /// The unknown cancels out and the sides still differ
+    NoSolution {
+        unknown: String,
+    },
+    /// The unknown cancels out and the sides are always equal
+    InfinitelyMany {
+        unknown: String,
+    },
+    /// A system whose equations contradict each other
+    Inconsistent,
+    /// A system without enough independent equations to pin down these unknowns
+    Underdetermined(Vec<String>),
+}
+
+impl fmt::Display for Unsolvable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Unsolvable::NoUnknown => write!(f, "there's no unknown to solve for"),
+            Unsolvable::TooManyUnknowns(names) => write!(
+                f,
+                "only one unknown can be solved for, found {}",
+                names.join(", ")
+            ),
+            Unsolvable::NonLinear { unknown, reason } => {
+                write!(f, "{} isn't linear - {}", unknown, reason)
+            }
+            Unsolvable::NoSolution { unknown } => {
+                write!(f, "no value of {} makes both sides equal", unknown)
+            }
+            Unsolvable::InfinitelyMany { unknown } => {
+                write!(f, "every value of {} makes both sides equal", unknown)
+            }
+            Unsolvable::Inconsistent => write!(f, "the equations contradict each other"),
+            Unsolvable::Underdetermined(names) => write!(
+                f,
+                "the equations don't determine {} - there are infinitely many solutions",
+                names.join(", ")
+            ),
+        }
+    }
+}
+
+/// Value of the unknown that makes both sides of an equation equal
+#[derive(Debug, Clone, PartialEq)]
+pub struct Solution {
+    pub unknown: String,
+    pub value: Number,
+}
+
+/// `coefficient * unknown + constant`
+struct Linear {
+    coefficient: Number,
+    constant: Number,
+}
+
+/// Solve an equation with a single unknown appearing linearly such as `2 * X + 3 = 11`
+/// Each side is reduced to `a * X + b` with the numeric work done by `evaluator` so the services
+/// do the arithmetic, then `X = (b2 - b1) / (a1 - a2)`
+/// Integer domains are solved exactly as rationals - the solution then has to be a whole number
+pub async fn solve<E, V>(evaluator: &V, ctx: &Context, equation: Equation) -> Result<Solution, E>
+where
+    E: From<Error> + Send + Sync,
+    V: MathASTEvaluator<E> + Sync,
+{
+    let unknown = {
+        let names = equation.variables();
+        match names.len() {
+            0 => return Err(unsolvable(Unsolvable::NoUnknown)),
+            1 => names.into_iter().next().expect("one name").to_string(),
+            _ => {
+                let names = names.into_iter().map(str::to_string).collect();
+                return Err(unsolvable(Unsolvable::TooManyUnknowns(names)));
+            }
+        }
+    };
+
+    let exact = ctx.exact();
+
+    let (lhs, rhs) = try_join!(
+        linear(evaluator, &exact, &unknown, equation.lhs),
+        linear(evaluator, &exact, &unknown, equation.rhs)
+    )?;
+    let (coefficient, constant) = try_join!(
+        evaluator.subtract(&exact, lhs.coefficient, rhs.coefficient),
+        evaluator.subtract(&exact, rhs.constant, lhs.constant)
+    )?;
+
+    if coefficient.is_zero() {
+        return Err(unsolvable(if constant.is_zero() {
+            Unsolvable::InfinitelyMany { unknown }
+        } else {
+            Unsolvable::NoSolution { unknown }
+        }));
+    }
+
+    let value = evaluator.divide(&exact, constant, coefficient).await?;
+    Ok(Solution {
+        unknown,
+        value: value.coerce(ctx)?,
+    })
+}
+
+fn unsolvable<E: From<Error>>(reason: Unsolvable) -> E {
+    Error::Unsolvable(reason).into()
+}
+
+/// Reduce a side of the equation to `a * unknown + b`
+/// Subtrees without the unknown are evaluated whole, the rest is combined coefficient-wise
+fn linear<'a, E, V>(
+    evaluator: &'a V,
+    ctx: &'a Context,
+    unknown: &'a str,
+    ast: MathAST,
+) -> BoxFuture<'a, Result<Linear, E>>
+where
+    E: From<Error> + Send + Sync,
+    V: MathASTEvaluator<E> + Sync,
+{
+    async move {
+        if !ast.variables().contains(unknown) {
+            return Ok(Linear {
+                coefficient: Number::from(0).coerce(ctx)?,
+                constant: evaluator.eval(ctx, ast).await?,
+            });
+        }
+
+        let non_linear = |reason: &str| {
+            unsolvable(Unsolvable::NonLinear {
+                unknown: unknown.to_string(),
+                reason: reason.to_string(),
+            })
+        };
+
+        match ast {
+            MathAST::Var(_) => Ok(Linear {
+                coefficient: Number::from(1).coerce(ctx)?,
+                constant: Number::from(0).coerce(ctx)?,
+            }),
+            MathAST::Negate(v) => {
+                let v = linear(evaluator, ctx, unknown, *v).await?;
+                Ok(Linear {
+                    coefficient: (-v.coefficient).coerce(ctx)?,
+                    constant: (-v.constant).coerce(ctx)?,
+                })
+            }
+            MathAST::UnaryPlus(v) => linear(evaluator, ctx, unknown, *v).await,
+            MathAST::Add(f, s) => {
+                let (f, s) = try_join!(
+                    linear(evaluator, ctx, unknown, *f),
+                    linear(evaluator, ctx, unknown, *s)
+                )?;
+                let (coefficient, constant) = try_join!(
+                    evaluator.add(ctx, f.coefficient, s.coefficient),
+                    evaluator.add(ctx, f.constant, s.constant)
+                )?;
+                Ok(Linear {
+                    coefficient,
+                    constant,
+                })
+            }
+            MathAST::Subtract(f, s) => {
+                let (f, s) = try_join!(
+                    linear(evaluator, ctx, unknown, *f),
+                    linear(evaluator, ctx, unknown, *s)
+                )?;
+                let (coefficient, constant) = try_join!(
+                    evaluator.subtract(ctx, f.coefficient, s.coefficient),
+                    evaluator.subtract(ctx, f.constant, s.constant)
+                )?;
+                Ok(Linear {
+                    coefficient,
+                    constant,
+                })
+            }
+            MathAST::Multiply(f, s) => {
+                let (f, s) = try_join!(
+                    linear(evaluator, ctx, unknown, *f),
+                    linear(evaluator, ctx, unknown, *s)
+                )?;
+                // One side has to be constant for the product to stay linear
+                let (v, factor) = match (f.coefficient.is_zero(), s.coefficient.is_zero()) {
+                    (true, _) => (s, f.constant),
+                    (_, true) => (f, s.constant),
+                    _ => return Err(non_linear("it's multiplied by itself")),
+                };
+                let (coefficient, constant) = try_join!(
+                    evaluator.multiply(ctx, v.coefficient, factor.clone()),
+                    evaluator.multiply(ctx, v.constant, factor)
+                )?;
+                Ok(Linear {
+                    coefficient,
+                    constant,
+                })
+            }
+            MathAST::Divide(f, s) => {
+                if s.variables().contains(unknown) {
+                    return Err(non_linear("it appears in a divisor"));
+                }
+                let (f, divisor) =
+                    try_join!(linear(evaluator, ctx, unknown, *f), evaluator.eval(ctx, *s))?;
+                let (coefficient, constant) = try_join!(
+                    evaluator.divide(ctx, f.coefficient, divisor.clone()),
+                    evaluator.divide(ctx, f.constant, divisor)
+                )?;
+                Ok(Linear {
+                    coefficient,
+                    constant,
+                })
+            }
+            MathAST::Power(..) => Err(non_linear("it appears in a power")),
+            MathAST::Remainder(..) | MathAST::FloorDivide(..) => {
+                Err(non_linear("it appears in a remainder or floor division"))
+            }
+            MathAST::Call { name, .. } => Err(non_linear(&format!(
+                "it appears in an argument to {}()",
+                name
+            ))),
+            MathAST::Value(_) => unreachable!("values never contain the unknown"),
+        }
+    }
+    .boxed()
+}
+
+/// `sum of coefficient * variable + constant` in exact arithmetic
+#[derive(Default)]
+struct LinearForm {
+    coefficients: BTreeMap<String, BigRational>,
+    constant: BigRational,
+}
+
+impl LinearForm {
+    fn scale(mut self, factor: &BigRational) -> Self {
+        self.coefficients.values_mut().for_each(|c| *c *= factor);
+        self.constant *= factor;
+        self
+    }
+
+    fn add(mut self, other: LinearForm) -> Self {
+        for (name, c) in other.coefficients {
+            *self.coefficients.entry(name).or_default() += c;
+        }
+        self.constant += other.constant;
+        self
+    }
+
+    fn is_constant(&self) -> bool {
+        self.coefficients.values().all(Zero::is_zero)
+    }
+}
+
+/// Solve a system of linear equations by Gaussian elimination in exact rational arithmetic
+/// Subexpressions without variables are evaluated by `evaluator` first so the services still do
+/// that work, the elimination itself is local
+/// Solutions are ordered by name and converted to the domain of `ctx`
+pub async fn solve_system<E, V>(
+    evaluator: &V,
+    ctx: &Context,
+    equations: Vec<Equation>,
+) -> Result<Vec<Solution>, E>
+where
+    E: From<Error> + Send + Sync,
+    V: MathASTEvaluator<E> + Sync,
+{
+    let unknowns: Vec<String> = equations
+        .iter()
+        .flat_map(Equation::variables)
+        .map(str::to_string)
+        .collect::<std::collections::BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    if unknowns.is_empty() {
+        return Err(unsolvable(Unsolvable::NoUnknown));
+    }
+
+    let exact = Context {
+        domain: NumericDomain::Rational,
+        ..ctx.clone()
+    };
+    let forms = futures::future::try_join_all(equations.into_iter().map(|equation| async {
+        let (lhs, rhs) = try_join!(
+            linear_form(evaluator, &exact, equation.lhs),
+            linear_form(evaluator, &exact, equation.rhs)
+        )?;
+        Ok::<_, E>(lhs.add(rhs.scale(&-BigRational::one())))
+    }))
+    .await?;
+
+    // One row per equation - a column per unknown then the constant moved to the right hand side
+    let mut rows: Vec<Vec<BigRational>> = forms
+        .into_iter()
+        .map(|mut form| {
+            let mut row: Vec<_> = unknowns
+                .iter()
+                .map(|name| form.coefficients.remove(name).unwrap_or_default())
+                .collect();
+            row.push(-form.constant);
+            row
+        })
+        .collect();
+    let pivots = eliminate(&mut rows, unknowns.len());
+
+    // Rows past the pivots have no coefficients left so anything but 0 = 0 is a contradiction
+    if rows[pivots.len()..]
+        .iter()
+        .any(|row| !row[unknowns.len()].is_zero())
+    {
+        return Err(unsolvable(Unsolvable::Inconsistent));
+    }
+    if pivots.len() < unknowns.len() {
+        let free = unknowns
+            .iter()
+            .enumerate()
+            .filter(|(column, _)| !pivots.contains(column))
+            .map(|(_, name)| name.clone())
+            .collect();
+        return Err(unsolvable(Unsolvable::Underdetermined(free)));
+    }
+
+    unknowns
+        .into_iter()
+        .zip(rows)
+        .map(|(unknown, row)| {
+            let value = Number::Rational(row.last().expect("constant column").clone());
+            Ok(Solution {
+                unknown,
+                value: value.coerce(ctx)?,
+            })
+        })
+        .collect()
+}
+
+/// Reduce `rows` to reduced row echelon form over their first `columns` columns
+/// Returns the pivot column of each leading row in order
+fn eliminate(rows: &mut [Vec<BigRational>], columns: usize) -> Vec<usize> {
+    let mut pivots = Vec::new();
+
+    for column in 0..columns {
+        let row = pivots.len();
+        let pivot = match (row..rows.len()).find(|&r| !rows[r][column].is_zero()) {
+            Some(pivot) => pivot,
+            None => continue,
+        };
+        rows.swap(row, pivot);
+
+        let lead = rows[row][column].clone();
+        rows[row].iter_mut().for_each(|v| *v /= &lead);
+        let pivot_row = rows[row].clone();
+        for (other, values) in rows.iter_mut().enumerate() {
+            let factor = values[column].clone();
+            if other == row || factor.is_zero() {
+                continue;
+            }
+            for (value, lead) in values.iter_mut().zip(&pivot_row).skip(column) {
+                *value -= lead * &factor;
+            }
+        }
+
+        pivots.push(column);
+        if pivots.len() == rows.len() {
+            break;
+        }
+    }
+
+    pivots
+}
+
+/// Reduce an expression to a linear combination of its variables
+fn linear_form<'a, E, V>(
+    evaluator: &'a V,
+    ctx: &'a Context,
+    ast: MathAST,
+) -> BoxFuture<'a, Result<LinearForm, E>>
+where
+    E: From<Error> + Send + Sync,
+    V: MathASTEvaluator<E> + Sync,
+{
+    async move {
+        let variables = ast.variables();
+        let unknown = match variables.iter().next() {
+            Some(unknown) => unknown.to_string(),
+            None => {
+                let value = evaluator.eval(ctx, ast).await?;
+                return Ok(LinearForm {
+                    constant: value.to_rational().expect("rationals are always finite"),
+                    ..Default::default()
+                });
+            }
+        };
+        let non_linear = |reason: &str| {
+            unsolvable(Unsolvable::NonLinear {
+                unknown: unknown.clone(),
+                reason: reason.to_string(),
+            })
+        };
+
+        match ast {
+            MathAST::Var(name) => Ok(LinearForm {
+                coefficients: BTreeMap::from([(name, BigRational::one())]),
+                ..Default::default()
+            }),
+            MathAST::Negate(v) => Ok(linear_form(evaluator, ctx, *v)
+                .await?
+                .scale(&-BigRational::one())),
+            MathAST::UnaryPlus(v) => linear_form(evaluator, ctx, *v).await,
+            MathAST::Add(f, s) => {
+                let (f, s) = try_join!(
+                    linear_form(evaluator, ctx, *f),
+                    linear_form(evaluator, ctx, *s)
+                )?;
+                Ok(f.add(s))
+            }
+            MathAST::Subtract(f, s) => {
+                let (f, s) = try_join!(
+                    linear_form(evaluator, ctx, *f),
+                    linear_form(evaluator, ctx, *s)
+                )?;
+                Ok(f.add(s.scale(&-BigRational::one())))
+            }
+            MathAST::Multiply(f, s) => {
+                let (f, s) = try_join!(
+                    linear_form(evaluator, ctx, *f),
+                    linear_form(evaluator, ctx, *s)
+                )?;
+                match (f.is_constant(), s.is_constant()) {
+                    (true, _) => Ok(s.scale(&f.constant)),
+                    (_, true) => Ok(f.scale(&s.constant)),
+                    _ => Err(non_linear("it's multiplied by a variable")),
+                }
+            }
+            MathAST::Divide(f, s) => {
+                if !s.variables().is_empty() {
+                    return Err(non_linear("a variable appears in a divisor"));
+                }
+                let (f, divisor) =
+                    try_join!(linear_form(evaluator, ctx, *f), evaluator.eval(ctx, *s))?;
+                if divisor.is_zero() {
+                    return Err(Error::DivisionByZero.into());
+                }
+                let divisor = divisor.to_rational().expect("rationals are always finite");
+                Ok(f.scale(&divisor.recip()))
+            }
+            MathAST::Power(..) => Err(non_linear("it appears in a power")),
+            MathAST::Remainder(..) | MathAST::FloorDivide(..) => {
+                Err(non_linear("it appears in a remainder or floor division"))
+            }
+            MathAST::Call { name, .. } => Err(non_linear(&format!(
+                "it appears in an argument to {}()",
+                name
+            ))),
+            MathAST::Value(_) => unreachable!("values never contain a variable"),
+        }
+    }
+    .boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+
+    use super::*;
+    use crate::{
+        parse::{parse_equation, parse_system, tests::TestASTEvaluator},
+        proto::equation::Rounding,
+    };
+
+    async fn solve_system_str(ctx: &Context, input: &str) -> Result<Vec<Solution>, Error> {
+        let equations = parse_system(input).expect("System");
+        solve_system(&TestASTEvaluator::default(), ctx, equations).await
+    }
+
+    fn assignments(solution: Vec<Solution>) -> Vec<(String, String)> {
+        solution
+            .into_iter()
+            .map(|s| (s.unknown, s.value.to_string()))
+            .collect()
+    }
+
+    async fn solve_str(ctx: &Context, input: &str) -> Result<Solution, Error> {
+        let equation = parse_equation(input).expect("Equation");
+        solve(&TestASTEvaluator::default(), ctx, equation).await
+    }
+
+    #[actix_rt::test]
+    async fn test_solve() {
+        let ctx = Context::default();
+
+        let solution = solve_str(&ctx, "2 * X + 3 = 11").await.unwrap();
+        assert_eq!(solution.unknown, "X");
+        assert_eq!(solution.value, 4.into());
+
+        // The exercise equation
+        let solution = solve_str(&ctx, "( ( (3 + 3)*2) /4) – 2 = X").await.unwrap();
+        assert_eq!(solution.value, 1.into());
+
+        let solution = solve_str(&ctx, "(x - 4) / 3 = -(x + 2) * 2 + 3 ^ 2 + 3")
+            .await
+            .unwrap();
+        assert_eq!(solution.value, 4.into());
+
+        // Solved exactly even where integer division would truncate
+        let solution = solve_str(&ctx, "x / 2 + x / 3 = 5").await.unwrap();
+        assert_eq!(solution.value, 6.into());
+
+        let ctx = Context::decimal(2, Rounding::HalfEven);
+        let solution = solve_str(&ctx, "3 * y = 1").await.unwrap();
+        assert_eq!(solution.value.to_string(), "0.33");
+    }
+
+    #[actix_rt::test]
+    async fn test_solve_unsolvable() {
+        let ctx = Context::default();
+        let reason = |res: Result<Solution, Error>| match res {
+            Err(Error::Unsolvable(reason)) => reason,
+            res => panic!("Expected an unsolvable equation, got {:?}", res),
+        };
+
+        assert_eq!(
+            reason(solve_str(&ctx, "x * (x + 1) = 2").await).to_string(),
+            "x isn't linear - it's multiplied by itself"
+        );
+        assert_matches!(
+            reason(solve_str(&ctx, "1 / x = 2").await),
+            Unsolvable::NonLinear { .. }
+        );
+        assert_matches!(
+            reason(solve_str(&ctx, "sqrt(x) = 2").await),
+            Unsolvable::NonLinear { .. }
+        );
+        assert_eq!(
+            reason(solve_str(&ctx, "2 * x + 1 = x + x").await),
+            Unsolvable::NoSolution {
+                unknown: "x".to_string()
+            }
+        );
+        assert_eq!(
+            reason(solve_str(&ctx, "2 * (x + 1) = x + x + 2").await),
+            Unsolvable::InfinitelyMany {
+                unknown: "x".to_string()
+            }
+        );
+        assert_eq!(
+            reason(solve_str(&ctx, "x + y = 1").await),
+            Unsolvable::TooManyUnknowns(vec!["x".to_string(), "y".to_string()])
+        );
+        assert_eq!(
+            reason(solve_str(&ctx, "1 + 1 = 2").await),
+            Unsolvable::NoUnknown
+        );
+
+        // A fractional solution isn't a 32 bit integer
+        assert_matches!(
+            solve_str(&ctx, "2 * x = 3").await,
+            Err(Error::OutOfDomain { .. })
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_solve_system() {
+        let ctx = Context::default();
+        let pair = |name: &str, value: &str| (name.to_string(), value.to_string());
+
+        let solution = solve_system_str(&ctx, "{2 * x + y = 5, x - y = 1}")
+            .await
+            .unwrap();
+        assert_eq!(assignments(solution), [pair("x", "2"), pair("y", "1")]);
+
+        // Pivots are found past a zero and constants are evaluated before elimination
+        let solution = solve_system_str(
+            &ctx,
+            "{y + z = 2 ^ 2, x + y + z = 6, (x - z) / 2 = -max(1, 0.5 * 2)}",
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            assignments(solution),
+            [pair("x", "2"), pair("y", "0"), pair("z", "4")]
+        );
+
+        // Redundant equations are fine as long as they agree
+        let ctx = Context::new(NumericDomain::Rational);
+        let solution = solve_system_str(&ctx, "{a + b = 1, a - b = 0, 2 * a + 2 * b = 2}")
+            .await
+            .unwrap();
+        assert_eq!(assignments(solution), [pair("a", "1/2"), pair("b", "1/2")]);
+
+        // A fraction doesn't fit the default 32 bit integer domain
+        assert_matches!(
+            solve_system_str(&Context::default(), "{a + b = 1, a - b = 0}").await,
+            Err(Error::OutOfDomain { .. })
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_solve_system_unsolvable() {
+        let ctx = Context::default();
+        let reason = |res: Result<Vec<Solution>, Error>| match res {
+            Err(Error::Unsolvable(reason)) => reason,
+            res => panic!("Expected an unsolvable system, got {:?}", res),
+        };
+
+        assert_eq!(
+            reason(solve_system_str(&ctx, "{x + y = 1, 2 * x + 2 * y = 3}").await),
+            Unsolvable::Inconsistent
+        );
+        assert_eq!(
+            reason(solve_system_str(&ctx, "{x + y + z = 1, x - y = 2}").await),
+            Unsolvable::Underdetermined(vec!["z".to_string()])
+        );
+        assert_eq!(
+            reason(solve_system_str(&ctx, "{x + y = 1, 2 * x + 2 * y = 2}").await).to_string(),
+            "the equations don't determine y - there are infinitely many solutions"
+        );
+        assert_matches!(
+            reason(solve_system_str(&ctx, "{x * y = 1, x - y = 0}").await),
+            Unsolvable::NonLinear { .. }
+        );
+        assert_matches!(
+            solve_system_str(&ctx, "{x / (1 - 1) = 1}").await,
+            Err(Error::DivisionByZero)
+        );
+    }
+}
@@ -0,0 +1,164 @@
+use std::{fmt, time::Duration};
+
+use tonic::Status;
+
+use crate::{
+    number::Number,
+    parse::MathAST,
+    proto::equation::{Number as NumberMessage, Step as StepMessage},
+};
+
+/// One reduction in an evaluation trace along with the steps that produced its operands
+/// Only operations are steps, literals are already reduced
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    /// The subexpression reduced, as it was before evaluation
+    pub expr: MathAST,
+    /// Operator symbol, sign or function name
+    pub op: String,
+    pub operands: Vec<Number>,
+    pub result: Number,
+    /// Service that applied `op` - signs are applied wherever their subtree was evaluated
+    pub service: String,
+    /// Time applying `op` once the operands were known, operands evaluated concurrently with
+    /// their siblings would otherwise be counted twice
+    pub elapsed: Duration,
+    /// Steps that produced the operands in order - literal operands need none
+    pub steps: Vec<Step>,
+}
+
+impl Step {
+    /// Every step in the tree, this one first
+    pub fn flatten(&self) -> Vec<&Step> {
+        let mut steps = vec![self];
+        for step in &self.steps {
+            steps.extend(step.flatten());
+        }
+        steps
+    }
+
+    /// `op` applied to the operands - `12 / 4`, `-3`, `max(1, 2)`
+    fn reduction(&self) -> String {
+        let operands: Vec<String> = self.operands.iter().map(Number::to_string).collect();
+        match &self.expr {
+            MathAST::Call { .. } => format!("{}({})", self.op, operands.join(", ")),
+            MathAST::Negate(_) | MathAST::UnaryPlus(_) => format!("{}{}", self.op, operands[0]),
+            _ => operands.join(&format!(" {} ", self.op)),
+        }
+    }
+
+    fn write_tree(&self, f: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+        writeln!(
+            f,
+            "{:indent$}{}: {} = {} [{}, {:?}]",
+            "",
+            self.expr,
+            self.reduction(),
+            self.result,
+            self.service,
+            self.elapsed,
+            indent = depth * 2
+        )?;
+        for step in &self.steps {
+            step.write_tree(f, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+/// One line per step, indented under the step that used its result
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_tree(f, 0)
+    }
+}
+
+impl From<Step> for StepMessage {
+    fn from(value: Step) -> Self {
+        Self {
+            expr: Some(value.expr.into()),
+            op: value.op,
+            operands: value
+                .operands
+                .into_iter()
+                .map(NumberMessage::from)
+                .collect(),
+            result: Some(value.result.into()),
+            service: value.service,
+            elapsed_micros: value.elapsed.as_micros() as u64,
+            steps: value.steps.into_iter().map(StepMessage::from).collect(),
+        }
+    }
+}
+
+impl TryFrom<StepMessage> for Step {
+    type Error = Status;
+
+    fn try_from(value: StepMessage) -> Result<Self, Self::Error> {
+        Ok(Self {
+            expr: value
+                .expr
+                .ok_or_else(|| Status::invalid_argument("Step with no expression"))?
+                .try_into()?,
+            op: value.op,
+            operands: value
+                .operands
+                .into_iter()
+                .map(Number::try_from)
+                .collect::<Result<_, _>>()?,
+            result: value
+                .result
+                .ok_or_else(|| Status::invalid_argument("Step with no result"))?
+                .try_into()?,
+            service: value.service,
+            elapsed: Duration::from_micros(value.elapsed_micros),
+            steps: value
+                .steps
+                .into_iter()
+                .map(Step::try_from)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::parse;
+
+    fn step(input: &str, operands: Vec<Number>, result: i32, steps: Vec<Step>) -> Step {
+        let expr = parse(input).unwrap();
+        let (op, service) = match expr.as_binary() {
+            Some((op, ..)) => (op.symbol().to_string(), op.service().to_string()),
+            None => ("-".to_string(), "Orchestrator".to_string()),
+        };
+        Step {
+            expr,
+            op,
+            operands,
+            result: result.into(),
+            service,
+            elapsed: Duration::from_micros(250),
+            steps,
+        }
+    }
+
+    #[test]
+    fn test_step() {
+        let add = step("2 + 1", vec![2.into(), 1.into()], 3, vec![]);
+        let sign = step("-(2 + 1)", vec![3.into()], -3, vec![add]);
+        let root = step("-(2 + 1) * 4", vec![(-3).into(), 4.into()], -12, vec![sign]);
+
+        assert_eq!(
+            root.to_string(),
+            "-(2 + 1) * 4: -3 * 4 = -12 [Multiplier, 250µs]\n\
+             \x20 -(2 + 1): -3 = -3 [Orchestrator, 250µs]\n\
+             \x20   2 + 1: 2 + 1 = 3 [Adder, 250µs]\n"
+        );
+        assert_eq!(root.flatten().len(), 3);
+
+        let message = StepMessage::from(root.clone());
+        assert_eq!(message.elapsed_micros, 250);
+        assert_eq!(Step::try_from(message).unwrap(), root);
+    }
+}
@@ -2,4 +2,8 @@
 cargo run --package adder & \
 cargo run --package subtractor & \
 cargo run --package multiplier & \
-cargo run --package divider
+cargo run --package divider & \
+cargo run --package power & \
+cargo run --package modulo & \
+cargo run --package functions & \
+cargo run --package solver
@@ -0,0 +1,5 @@
+use equation::service::{serve, Modulo};
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    serve::<Modulo>()
+}
@@ -0,0 +1,252 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt,
+};
+
+use equation::{
+    dag::{Dag, Node, NodeId},
+    parse::MathAST,
+};
+
+/// Who applies signs at the root and sends everything else out
+pub(crate) const ORCHESTRATOR: &str = "Orchestrator";
+const FUNCTIONS: &str = "Functions";
+
+/// What evaluating a tree will cost without evaluating it
+/// The orchestrator sends the tree under any leading signs to the service owning its root, that
+/// service applies its own operators and delegates every other operation (with values for
+/// operands) to the service owning it - operands are worked out concurrently and a repeated
+/// subexpression only once
+#[derive(Debug)]
+pub(crate) struct Plan {
+    pub(crate) ast: MathAST,
+    /// Service the orchestrator sends the tree to - none when it's a literal
+    pub(crate) entry: Option<&'static str>,
+    /// Remote calls each service makes, by caller
+    pub(crate) calls: BTreeMap<&'static str, usize>,
+    /// Longest chain of remote calls that each wait on the one before
+    pub(crate) critical_path: usize,
+    /// Operands of the same operation that each make a remote call none of the others wait on
+    /// and so overlap - innermost first
+    pub(crate) parallel: Vec<Vec<MathAST>>,
+}
+
+/// What evaluating a node involves
+#[derive(Debug, Clone, Default)]
+struct Cost {
+    /// Longest chain of remote calls that each wait on the one before
+    depth: usize,
+    /// Every remote call the node waits on, its own included
+    calls: BTreeSet<NodeId>,
+}
+
+impl Plan {
+    pub(crate) fn new(ast: MathAST) -> Self {
+        let mut plan = Plan {
+            ast,
+            entry: None,
+            calls: BTreeMap::new(),
+            critical_path: 0,
+            parallel: Vec::new(),
+        };
+
+        let mut root = &plan.ast;
+        while let MathAST::Negate(v) | MathAST::UnaryPlus(v) = root {
+            root = v;
+        }
+        let dag = Dag::new(root);
+        let entry = match owner(dag.node(dag.root())) {
+            Some(entry) => entry,
+            None => return plan,
+        };
+
+        plan.entry = Some(entry);
+        plan.calls.insert(ORCHESTRATOR, 1);
+        plan.critical_path = 1 + plan
+            .walk(&dag, dag.root(), entry, &mut HashMap::new())
+            .depth;
+        plan
+    }
+
+    /// Count the calls `service` makes evaluating node `id`, returning what the node costs
+    /// `seen` holds the cost of every node already counted
+    fn walk(
+        &mut self,
+        dag: &Dag,
+        id: NodeId,
+        service: &'static str,
+        seen: &mut HashMap<NodeId, Cost>,
+    ) -> Cost {
+        if let Some(cost) = seen.get(&id) {
+            return cost.clone();
+        }
+        let node = dag.node(id);
+        let operands: Vec<NodeId> = match node {
+            Node::Value(_) | Node::Var(_) => return Cost::default(),
+            Node::Negate(v) | Node::UnaryPlus(v) => vec![*v],
+            Node::Call { args, .. } => args.clone(),
+            Node::Binary(_, first, second) => vec![*first, *second],
+        };
+        let costs: Vec<Cost> = operands
+            .iter()
+            .map(|operand| self.walk(dag, *operand, service, seen))
+            .collect();
+
+        // Operands are worked out concurrently but one only waiting on calls another operand
+        // makes too (a shared subexpression is evaluated once) has nothing of its own in flight
+        let busy: Vec<MathAST> = operands
+            .iter()
+            .zip(&costs)
+            .enumerate()
+            .filter(|(i, (_, cost))| {
+                cost.calls.iter().any(|call| {
+                    costs
+                        .iter()
+                        .enumerate()
+                        .all(|(j, other)| j == *i || !other.calls.contains(call))
+                })
+            })
+            .map(|(_, (operand, _))| dag.to_ast(*operand))
+            .collect();
+        if busy.len() > 1 {
+            self.parallel.push(busy);
+        }
+
+        let mut cost = Cost {
+            depth: costs.iter().map(|cost| cost.depth).max().unwrap_or(0),
+            calls: costs.into_iter().flat_map(|cost| cost.calls).collect(),
+        };
+        if matches!(owner(node), Some(owner) if owner != service) {
+            *self.calls.entry(service).or_default() += 1;
+            cost.depth += 1;
+            cost.calls.insert(id);
+        }
+        seen.insert(id, cost.clone());
+        cost
+    }
+}
+
+/// Service that applies the operation at `node` - signs are applied by whoever reaches them
+fn owner(node: &Node) -> Option<&'static str> {
+    match node {
+        Node::Call { .. } => Some(FUNCTIONS),
+        Node::Binary(op, ..) => Some(op.service()),
+        _ => None,
+    }
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Plan for {}", self.ast)?;
+        let entry = match self.entry {
+            Some(entry) => entry,
+            None => return writeln!(f, "  Nothing to send, there are no operations to evaluate"),
+        };
+        writeln!(f, "  Entered first: {}", entry)?;
+        writeln!(f, "  Remote calls:")?;
+        for (service, calls) in &self.calls {
+            writeln!(f, "    {}: {}", service, calls)?;
+        }
+        writeln!(f, "  Critical path: {} remote calls", self.critical_path)?;
+        if self.parallel.is_empty() {
+            return writeln!(
+                f,
+                "  In parallel: nothing, every remote call waits on the last"
+            );
+        }
+        writeln!(f, "  In parallel:")?;
+        for subtrees in &self.parallel {
+            let subtrees: Vec<String> = subtrees.iter().map(MathAST::to_string).collect();
+            writeln!(f, "    {}", subtrees.join(" | "))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use equation::parse::parse;
+
+    fn plan(input: &str) -> Plan {
+        Plan::new(parse(input).unwrap())
+    }
+
+    fn calls(calls: &[(&'static str, usize)]) -> BTreeMap<&'static str, usize> {
+        calls.iter().copied().collect()
+    }
+
+    #[test]
+    fn test_plan() {
+        // The subtractor delegates everything below its root in turn
+        let exercise = plan("( ( (3 + 3)*2) /4) - 2");
+        assert_eq!(exercise.entry, Some("Subtractor"));
+        assert_eq!(
+            exercise.calls,
+            calls(&[("Orchestrator", 1), ("Subtractor", 3)])
+        );
+        assert_eq!(exercise.critical_path, 4);
+        assert!(exercise.parallel.is_empty());
+
+        // Operations the entered service owns cost nothing more
+        let product = plan("2 * x * 4");
+        assert_eq!(product.entry, Some("Multiplier"));
+        assert_eq!(product.calls, calls(&[("Orchestrator", 1)]));
+        assert_eq!(product.critical_path, 1);
+
+        let sum = plan("-(a * b + max(c / d, 1))");
+        assert_eq!(sum.entry, Some("Adder"));
+        assert_eq!(sum.calls, calls(&[("Adder", 3), ("Orchestrator", 1)]));
+        assert_eq!(sum.critical_path, 3);
+        assert_eq!(
+            sum.parallel,
+            vec![vec![
+                parse("a * b").unwrap(),
+                parse("max(c / d, 1)").unwrap()
+            ]]
+        );
+    }
+
+    #[test]
+    fn test_plan_shared() {
+        // `a * b` is delegated once and waited on by both of its users, alongside `c - d`
+        let shared = plan("(a * b) + (a * b) * (c - d)");
+        assert_eq!(shared.entry, Some("Adder"));
+        assert_eq!(shared.calls, calls(&[("Adder", 3), ("Orchestrator", 1)]));
+        assert_eq!(shared.critical_path, 3);
+        assert_eq!(
+            shared.parallel,
+            vec![vec![parse("a * b").unwrap(), parse("c - d").unwrap()]]
+        );
+
+        // Users of a shared call that each make calls of their own still overlap
+        let both = plan("(a * b - c) * (a * b - d)");
+        assert_eq!(
+            both.parallel,
+            vec![vec![
+                parse("a * b - c").unwrap(),
+                parse("a * b - d").unwrap()
+            ]]
+        );
+        assert!(plan("a * b + (a * b) * c").parallel.is_empty());
+    }
+
+    #[test]
+    fn test_plan_display() {
+        assert_eq!(
+            plan("(a * b) + (c * d)").to_string(),
+            "Plan for a * b + c * d\n\
+             \x20 Entered first: Adder\n\
+             \x20 Remote calls:\n\
+             \x20   Adder: 2\n\
+             \x20   Orchestrator: 1\n\
+             \x20 Critical path: 2 remote calls\n\
+             \x20 In parallel:\n\
+             \x20   a * b | c * d\n"
+        );
+        assert_eq!(
+            plan("-7").to_string(),
+            "Plan for -7\n  Nothing to send, there are no operations to evaluate\n"
+        );
+    }
+}
@@ -1,38 +1,300 @@
+use async_trait::async_trait;
+use clap::{error::ErrorKind, CommandFactory, Parser, ValueEnum};
 use equation::{
     client::{
-        build_adder_client, build_divider_client, build_multiplier_client, build_subtractor_client,
+        build_adder_client, build_divider_client, build_functions_client, build_modulo_client,
+        build_multiplier_client, build_power_client, build_solver_client, build_subtractor_client,
     },
     config::Config,
-    parse::{test_value, MathAST},
+    number::Number,
+    parse::{
+        parse, parse_equation, parse_rpn, parse_sexpr, parse_system, Bindings, Context, Equation,
+        MathAST, MathASTEvaluator, ParseError, MAX_SCALE,
+    },
     proto::equation::{
         adder_client::AdderClient, divider_client::DividerClient,
-        multiplier_client::MultiplierClient, subtractor_client::SubtractorClient,
-        CalculationRequest, Empty,
+        functions_client::FunctionsClient, modulo_client::ModuloClient,
+        multiplier_client::MultiplierClient, power_client::PowerClient,
+        solver_client::SolverClient, subtractor_client::SubtractorClient, CalculationRequest,
+        CallRequest, DerivativeRequest, Empty, NumericDomain, Outcome, RemainderConvention,
+        Rounding, SystemRequest,
     },
-    server::wait_for_ctrl_c,
+    server::{wait_for_ctrl_c, Error},
+    simplify::simplify,
+    solve::{solve, Solution},
+    trace::Step,
 };
+use explain::{Plan, ORCHESTRATOR};
+use std::time::Instant;
 use tokio::{spawn, sync::mpsc};
 use tonic::{transport::Channel, Status};
 
+mod explain;
+
+/// Equation from the exercise - used when no equation is passed on the command line
+const EXERCISE: &str = "( ( (3 + 3)*2) /4) - 2";
+
+/// Evaluate an equation across the operator micro services
+#[derive(Parser, Debug)]
+struct Args {
+    /// Equation to evaluate - defaults to the exercise equation
+    /// Give one with `=` like `2 * X + 3 = 11` to solve for its unknown instead
+    /// or a system like `{2 * x + y = 5, x - y = 1}` to have the solver service solve it
+    #[arg(default_value = EXERCISE)]
+    equation: String,
+
+    /// Numeric type every value in the equation is evaluated as
+    #[arg(long, value_enum, default_value_t = Domain::Int32)]
+    domain: Domain,
+
+    /// Also print the result as a decimal rounded to this many places - handy for rationals
+    #[arg(long)]
+    decimal_places: Option<usize>,
+
+    /// Digits kept after the point when evaluating in the decimal domain
+    #[arg(long, default_value_t = 2, value_parser = clap::value_parser!(u32).range(..=MAX_SCALE as i64))]
+    scale: u32,
+
+    /// How the decimal domain rounds results that need more digits than `scale`
+    #[arg(long, value_enum, default_value_t = RoundingMode::HalfEven)]
+    rounding: RoundingMode,
+
+    /// Sign convention for `%` when the operands have different signs
+    #[arg(long, value_enum, default_value_t = Remainder::Truncated)]
+    remainder: Remainder,
+
+    /// Value for a variable in the equation as NAME=NUMBER - repeat for each variable
+    #[arg(long = "bind", value_name = "NAME=NUMBER", value_parser = parse_binding)]
+    bindings: Vec<(String, Number)>,
+
+    /// Send the equation to the services exactly as written - by default literal subtrees are
+    /// folded and identities like `x * 1` removed first, saving remote calls
+    #[arg(long)]
+    no_simplify: bool,
+
+    /// Print the gradient instead of the result - the derivative with respect to each variable,
+    /// worked out by the solver service and evaluated at the `--bind` values
+    #[arg(long)]
+    gradient: bool,
+
+    /// Print the evaluated equation with its result as LaTeX or MathML instead of text, ready to
+    /// embed in a document - only used when evaluating
+    #[arg(long, value_enum)]
+    typeset: Option<Notation>,
+
+    /// Syntax the equation is written in - equations with `=` and systems are always infix
+    #[arg(long, value_enum, default_value_t = Format::Infix)]
+    format: Format,
+
+    /// Also print every step of the evaluation as a tree - the operands and result of each
+    /// operation, the service that applied it and how long that took - only used when evaluating
+    #[arg(long)]
+    trace: bool,
+
+    /// Print the evaluation plan instead of evaluating - the service entered first, remote calls
+    /// made by each service, the longest chain of calls and which subtrees run in parallel
+    #[arg(long, conflicts_with = "gradient")]
+    explain: bool,
+}
+
+impl Args {
+    /// A system like `{x + y = 2, x - y = 0}` for the solver service - only ever infix
+    fn system(&self) -> bool {
+        matches!(self.format, Format::Infix) && self.equation.trim_start().starts_with('{')
+    }
+
+    /// An equation with `=` to solve rather than evaluate - only ever infix
+    fn solving(&self) -> bool {
+        matches!(self.format, Format::Infix) && self.equation.contains('=')
+    }
+
+    /// Conflicts clap can't see as they depend on the equation - there's no evaluation to plan
+    /// when solving
+    fn validate(&self) -> Result<(), clap::Error> {
+        if self.explain && (self.system() || self.solving()) {
+            return Err(Args::command().error(
+                ErrorKind::ArgumentConflict,
+                "--explain plans evaluations, it can't be used with an equation to solve or a system",
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Format {
+    /// Conventional notation like `(1 + 2) * 3`
+    Infix,
+    /// Postfix like `1 2 + 3 *` - signs are `neg` and `pos`, variadic functions `max:3`
+    Rpn,
+    /// Lisp style prefix like `(* (+ 1 2) 3)`
+    Sexpr,
+}
+
+impl Format {
+    fn parse(self, input: &str) -> Result<MathAST, ParseError> {
+        match self {
+            Format::Infix => parse(input),
+            Format::Rpn => parse_rpn(input),
+            Format::Sexpr => parse_sexpr(input),
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Notation {
+    /// Math mode markup without the surrounding `$`
+    Latex,
+    /// A Presentation MathML `<math>` element
+    Mathml,
+}
+
+/// `ast = result` in `notation` - the equation is shown as written, before bindings and simplifying
+fn typeset(notation: Notation, ast: MathAST, result: Number) -> String {
+    let equation = Equation {
+        lhs: ast,
+        rhs: MathAST::Value(result),
+    };
+    match notation {
+        Notation::Latex => equation.latex(),
+        Notation::Mathml => equation.mathml(),
+    }
+}
+
+/// How a single equation is evaluated beyond its Context
+struct Options {
+    bindings: Bindings,
+    /// Run equation::simplify over the tree before dispatching it
+    simplify: bool,
+    format: Format,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            bindings: Bindings::new(),
+            simplify: true,
+            format: Format::Infix,
+        }
+    }
+}
+
+/// Split NAME=NUMBER - the number may carry a sign like any literal in an equation
+fn parse_binding(arg: &str) -> Result<(String, Number), String> {
+    let (name, value) = arg
+        .split_once('=')
+        .ok_or_else(|| format!("expected NAME=NUMBER, found {}", arg))?;
+    let name = name.trim();
+
+    match parse(name) {
+        Ok(MathAST::Var(_)) => {}
+        _ => return Err(format!("{} isn't a valid variable name", name)),
+    }
+    match parse(value) {
+        Ok(MathAST::Value(v)) => Ok((name.to_string(), v)),
+        _ => Err(format!("{} isn't a number", value.trim())),
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Domain {
+    Int32,
+    Int64,
+    BigInteger,
+    Rational,
+    Float64,
+    Decimal,
+}
+
+impl From<Domain> for NumericDomain {
+    fn from(value: Domain) -> Self {
+        match value {
+            Domain::Int32 => NumericDomain::Int32,
+            Domain::Int64 => NumericDomain::Int64,
+            Domain::BigInteger => NumericDomain::BigInteger,
+            Domain::Rational => NumericDomain::Rational,
+            Domain::Float64 => NumericDomain::Float64,
+            Domain::Decimal => NumericDomain::Decimal,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum RoundingMode {
+    /// Ties go to the even neighbour (banker's rounding)
+    HalfEven,
+    /// Ties go away from zero
+    HalfUp,
+}
+
+impl From<RoundingMode> for Rounding {
+    fn from(value: RoundingMode) -> Self {
+        match value {
+            RoundingMode::HalfEven => Rounding::HalfEven,
+            RoundingMode::HalfUp => Rounding::HalfUp,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Remainder {
+    /// Takes the sign of the dividend, like Rust and C
+    Truncated,
+    /// Takes the sign of the divisor, like Python
+    Floored,
+    /// Never negative
+    Euclidean,
+}
+
+impl From<Remainder> for RemainderConvention {
+    fn from(value: Remainder) -> Self {
+        match value {
+            Remainder::Truncated => RemainderConvention::Truncated,
+            Remainder::Floored => RemainderConvention::Floored,
+            Remainder::Euclidean => RemainderConvention::Euclidean,
+        }
+    }
+}
+
+/// Note for the user about anything notable in a result
+fn describe(outcome: Outcome) -> &'static str {
+    match outcome {
+        Outcome::Nan => "not a number",
+        Outcome::Infinite => "infinite",
+        Outcome::Rounded => "rounded",
+        Outcome::Unspecified => "unspecified",
+    }
+}
+
 #[derive(Clone)]
 struct Clients {
     adder_client: AdderClient<Channel>,
     subtractor_client: SubtractorClient<Channel>,
     multiplier_client: MultiplierClient<Channel>,
     divider_client: DividerClient<Channel>,
+    power_client: PowerClient<Channel>,
+    modulo_client: ModuloClient<Channel>,
+    functions_client: FunctionsClient<Channel>,
+    solver_client: SolverClient<Channel>,
 }
 
 impl Clients {
     pub(crate) async fn new(config: &Config) -> Self {
         Self {
-            adder_client: build_adder_client(&config).await.expect("client connect"),
-            subtractor_client: build_subtractor_client(&config)
+            adder_client: build_adder_client(config).await.expect("client connect"),
+            subtractor_client: build_subtractor_client(config)
                 .await
                 .expect("client connect"),
-            multiplier_client: build_multiplier_client(&config)
+            multiplier_client: build_multiplier_client(config)
                 .await
                 .expect("cllinet connect"),
-            divider_client: build_divider_client(&config).await.expect("client connect"),
+            divider_client: build_divider_client(config).await.expect("client connect"),
+            power_client: build_power_client(config).await.expect("client connect"),
+            modulo_client: build_modulo_client(config).await.expect("client connect"),
+            functions_client: build_functions_client(config)
+                .await
+                .expect("client connect"),
+            solver_client: build_solver_client(config).await.expect("client connect"),
         }
     }
 
@@ -48,22 +310,226 @@ impl Clients {
         let _ = self.multiplier_client.term(message.clone()).await;
 
         println!("Sending term command to divider");
-        let _ = self.divider_client.term(message).await;
+        let _ = self.divider_client.term(message.clone()).await;
+
+        println!("Sending term command to power");
+        let _ = self.power_client.term(message.clone()).await;
+
+        println!("Sending term command to modulo");
+        let _ = self.modulo_client.term(message.clone()).await;
+
+        println!("Sending term command to functions");
+        let _ = self.functions_client.term(message.clone()).await;
+
+        println!("Sending term command to solver");
+        let _ = self.solver_client.term(message).await;
     }
 }
 
+/// The solver works a value at a time - each operation goes to its service
+/// Whole subtrees without the unknown are still handed over in one request by eval()
+#[async_trait]
+impl MathASTEvaluator<Status> for Clients {
+    async fn add(&self, ctx: &Context, first: Number, second: Number) -> Result<Number, Status> {
+        self.eval(ctx, operation(MathAST::Add, first, second)).await
+    }
+    async fn subtract(
+        &self,
+        ctx: &Context,
+        first: Number,
+        second: Number,
+    ) -> Result<Number, Status> {
+        self.eval(ctx, operation(MathAST::Subtract, first, second))
+            .await
+    }
+    async fn multiply(
+        &self,
+        ctx: &Context,
+        first: Number,
+        second: Number,
+    ) -> Result<Number, Status> {
+        self.eval(ctx, operation(MathAST::Multiply, first, second))
+            .await
+    }
+    async fn divide(&self, ctx: &Context, first: Number, second: Number) -> Result<Number, Status> {
+        self.eval(ctx, operation(MathAST::Divide, first, second))
+            .await
+    }
+    async fn power(&self, ctx: &Context, first: Number, second: Number) -> Result<Number, Status> {
+        self.eval(ctx, operation(MathAST::Power, first, second))
+            .await
+    }
+    async fn remainder(
+        &self,
+        ctx: &Context,
+        first: Number,
+        second: Number,
+    ) -> Result<Number, Status> {
+        self.eval(ctx, operation(MathAST::Remainder, first, second))
+            .await
+    }
+    async fn floor_divide(
+        &self,
+        ctx: &Context,
+        first: Number,
+        second: Number,
+    ) -> Result<Number, Status> {
+        self.eval(ctx, operation(MathAST::FloorDivide, first, second))
+            .await
+    }
+    async fn call(&self, ctx: &Context, name: &str, args: Vec<Number>) -> Result<Number, Status> {
+        let args = args.into_iter().map(MathAST::Value).collect();
+        self.eval(
+            ctx,
+            MathAST::Call {
+                name: name.to_string(),
+                args,
+            },
+        )
+        .await
+    }
+
+    async fn eval(&self, ctx: &Context, ast: MathAST) -> Result<Number, Status> {
+        run_equation(&mut self.clone(), ctx, ast).await
+    }
+
+    fn name(&self) -> &str {
+        ORCHESTRATOR
+    }
+}
+
+fn operation(
+    root: fn(Box<MathAST>, Box<MathAST>) -> MathAST,
+    first: Number,
+    second: Number,
+) -> MathAST {
+    root(
+        Box::new(MathAST::Value(first)),
+        Box::new(MathAST::Value(second)),
+    )
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse();
+    args.validate().unwrap_or_else(|e| e.exit());
     let config = Config::new();
     let mut clients = Clients::new(&config).await;
 
     let (signal_tx, mut signal_rx) = mpsc::channel(100);
     spawn(wait_for_ctrl_c(signal_tx));
 
-    // Test distributed equation with AST from exercise ( ( (3 + 3)*2) /4) – 2 = X
-    let ast = test_value();
-    let res = run_equation(&mut clients, ast).await?;
-    println!("The result of your equation is: {:?}", res);
+    // Evaluate the equation given as our first argument or fall back to ( ( (3 + 3)*2) /4) – 2 = X
+    let ctx = Context {
+        domain: args.domain.into(),
+        scale: args.scale,
+        rounding: args.rounding.into(),
+        remainder: args.remainder.into(),
+    };
+    let (system, solving) = (args.system(), args.solving());
+    let bindings: Bindings = args.bindings.into_iter().collect();
+    let evaluating = !(args.gradient || system || solving || args.explain);
+    let mut steps = None;
+    let outcome = if args.gradient {
+        let options = Options {
+            bindings,
+            simplify: !args.no_simplify,
+            format: args.format,
+        };
+        gradient(&mut clients, &ctx, &args.equation, &options)
+            .await
+            .map(|partials| {
+                partials
+                    .into_iter()
+                    .map(|p| (format!("  d/d{} = {} =", p.var, p.derivative), p.value))
+                    .collect()
+            })
+    } else if system {
+        solve_system(&mut clients, &ctx, &args.equation, &bindings)
+            .await
+            .map(|solution| {
+                solution
+                    .into_iter()
+                    .map(|s| (format!("  {} =", s.unknown), s.value))
+                    .collect()
+            })
+    } else if solving {
+        solve_equation(&clients, &ctx, &args.equation, &bindings)
+            .await
+            .map(|solution| {
+                let label = format!("The solution of your equation is: {} =", solution.unknown);
+                vec![(label, solution.value)]
+            })
+    } else if args.explain {
+        let options = Options {
+            bindings,
+            simplify: !args.no_simplify,
+            format: args.format,
+        };
+        explain(&ctx, &args.equation, &options).map(|plan| {
+            print!("{}", plan);
+            Vec::new()
+        })
+    } else {
+        let options = Options {
+            bindings,
+            simplify: !args.no_simplify,
+            format: args.format,
+        };
+        let evaluation = if args.trace {
+            trace(&mut clients, &ctx, &args.equation, &options).await
+        } else {
+            evaluate(&mut clients, &ctx, &args.equation, &options)
+                .await
+                .map(|res| (res, None))
+        };
+        evaluation.map(|(res, step)| {
+            steps = step;
+            vec![("The result of your equation is:".to_string(), res)]
+        })
+    };
+    let results: Vec<(String, Number)> = match outcome {
+        Ok(results) => results,
+        Err(status) => {
+            eprintln!("{}", status.message());
+            return Err(status.into());
+        }
+    };
+    if args.gradient {
+        println!("The gradient of your equation is:");
+    } else if system {
+        println!("The solution of your system is:");
+    }
+    match args.typeset {
+        Some(notation) if evaluating => {
+            let ast = args
+                .format
+                .parse(&args.equation)
+                .expect("the equation parsed to be evaluated");
+            let (_, res) = results
+                .into_iter()
+                .next()
+                .expect("one result per evaluation");
+            println!("{}", typeset(notation, ast, res));
+        }
+        _ => {
+            for (label, res) in results {
+                match args.decimal_places {
+                    Some(places) => {
+                        println!("{} {} ≈ {}", label, res, res.to_decimal_string(places))
+                    }
+                    None => println!("{} {}", label, res),
+                }
+                for outcome in res.outcomes() {
+                    println!("Note: the result is {}", describe(outcome));
+                }
+            }
+        }
+    }
+    if let Some(step) = steps {
+        println!("Steps taken:");
+        print!("{}", step);
+    }
 
     println!("Blocking on signal for CTRL-C");
     signal_rx.recv().await;
@@ -73,73 +539,316 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
-async fn run_equation(clients: &mut Clients, ast: MathAST) -> Result<i32, Status> {
+/// Parse an equation string and run it - parse failures become invalid argument statuses
+/// carrying the caret diagnostic so callers can show users exactly where their input is wrong
+/// Variables are substituted here so the services only ever see values
+async fn evaluate(
+    clients: &mut Clients,
+    ctx: &Context,
+    input: &str,
+    options: &Options,
+) -> Result<Number, Status> {
+    let ast = prepare(ctx, input, options)?;
+    run_equation(clients, ctx, ast).await
+}
+
+/// Evaluate like evaluate() with each service returning the steps it took, assembled into one
+/// tree - there's no Step when the equation is a literal once simplified
+async fn trace(
+    clients: &mut Clients,
+    ctx: &Context,
+    input: &str,
+    options: &Options,
+) -> Result<(Number, Option<Step>), Status> {
+    let ast = prepare(ctx, input, options)?;
+    run(clients, ctx, ast, true).await
+}
+
+/// Plan the evaluation of an equation string without contacting any service
+/// The tree is prepared as it would be for evaluate() so the plan matches what's sent, apart
+/// from variables without a binding being left in place
+fn explain(ctx: &Context, input: &str, options: &Options) -> Result<Plan, Status> {
+    let ast = options
+        .format
+        .parse(input)
+        .map_err(|e| e.to_status(input))?;
+    let mut ast = ast.substitute(&options.bindings);
+    if options.simplify {
+        ast = simplify(ast, ctx);
+    }
+    Ok(Plan::new(ast))
+}
+
+/// The tree sent to the services for an equation string
+fn prepare(ctx: &Context, input: &str, options: &Options) -> Result<MathAST, Status> {
+    let ast = options
+        .format
+        .parse(input)
+        .map_err(|e| e.to_status(input))?;
+    let mut ast = ast.bind(&options.bindings)?;
+    if options.simplify {
+        ast = simplify(ast, ctx);
+    }
+    println!("Evaluating {}", ast);
+    Ok(ast)
+}
+
+/// Parse `lhs = rhs` and solve for the one variable left once `bindings` are substituted
+async fn solve_equation(
+    clients: &Clients,
+    ctx: &Context,
+    input: &str,
+    bindings: &Bindings,
+) -> Result<Solution, Status> {
+    let equation = parse_equation(input).map_err(|e| e.to_status(input))?;
+    solve(clients, ctx, equation.substitute(bindings)).await
+}
+
+/// Parse `{lhs = rhs, ...}` and have the solver service solve for every variable left unbound
+async fn solve_system(
+    clients: &mut Clients,
+    ctx: &Context,
+    input: &str,
+    bindings: &Bindings,
+) -> Result<Vec<Solution>, Status> {
+    let equations = parse_system(input).map_err(|e| e.to_status(input))?;
+    let request = SystemRequest::new(equations, ctx).with_bindings(bindings);
+
+    let message = clients
+        .solver_client
+        .solve_system(request)
+        .await?
+        .into_inner();
+    message.into_solution()
+}
+
+/// One component of a gradient
+#[derive(Debug)]
+struct Partial {
+    var: String,
+    derivative: MathAST,
+    /// The derivative evaluated at the bindings
+    value: Number,
+}
+
+/// Parse an equation and have the solver service differentiate it with respect to each of its
+/// variables in turn - every derivative is then evaluated at `options.bindings` like any equation
+async fn gradient(
+    clients: &mut Clients,
+    ctx: &Context,
+    input: &str,
+    options: &Options,
+) -> Result<Vec<Partial>, Status> {
+    let ast = options
+        .format
+        .parse(input)
+        .map_err(|e| e.to_status(input))?;
+
+    let mut partials = Vec::new();
+    for var in ast.variables() {
+        let request = DerivativeRequest::new(ast.clone(), var, ctx);
+        let message = clients
+            .solver_client
+            .differentiate(request)
+            .await?
+            .into_inner();
+
+        let derivative = message.into_derivative()?;
+        let mut ast = derivative.clone().bind(&options.bindings)?;
+        if options.simplify {
+            ast = simplify(ast, ctx);
+        }
+        let value = run_equation(clients, ctx, ast).await?;
+        partials.push(Partial {
+            var: var.to_string(),
+            derivative,
+            value,
+        });
+    }
+    Ok(partials)
+}
+
+async fn run_equation(
+    clients: &mut Clients,
+    ctx: &Context,
+    ast: MathAST,
+) -> Result<Number, Status> {
+    Ok(run(clients, ctx, ast, false).await?.0)
+}
+
+/// Send each operation to its service - with `trace` the services return the steps they took
+async fn run(
+    clients: &mut Clients,
+    ctx: &Context,
+    ast: MathAST,
+    trace: bool,
+) -> Result<(Number, Option<Step>), Status> {
     match ast {
-        MathAST::Value(v) => Ok(v),
+        MathAST::Value(v) => Ok((v.coerce(ctx)?, None)),
+        MathAST::Var(name) => Err(Error::UnboundVariables(vec![name]).into()),
+        // Signs are applied here, the services only ever see what's underneath them
+        MathAST::Negate(_) | MathAST::UnaryPlus(_) => {
+            Box::pin(apply_sign(clients, ctx, ast, trace)).await
+        }
         MathAST::Add(first, second) => {
-            let request = tonic::Request::new(CalculationRequest {
-                first_arg: serde_json::to_string(&first).unwrap(),
-                second_arg: serde_json::to_string(&second).unwrap(),
-            });
+            let request = tonic::Request::new(
+                CalculationRequest::new(*first, *second, ctx).with_trace(trace),
+            );
 
             let message = clients.adder_client.add(request).await?.into_inner();
-            Ok(message.result)
+            message.into_traced_result()
         }
         MathAST::Subtract(first, second) => {
-            let request = tonic::Request::new(CalculationRequest {
-                first_arg: serde_json::to_string(&first).unwrap(),
-                second_arg: serde_json::to_string(&second).unwrap(),
-            });
+            let request = tonic::Request::new(
+                CalculationRequest::new(*first, *second, ctx).with_trace(trace),
+            );
 
             let message = clients
                 .subtractor_client
                 .subtract(request)
                 .await?
                 .into_inner();
-            Ok(message.result)
+            message.into_traced_result()
         }
         MathAST::Multiply(first, second) => {
-            let request = tonic::Request::new(CalculationRequest {
-                first_arg: serde_json::to_string(&first).unwrap(),
-                second_arg: serde_json::to_string(&second).unwrap(),
-            });
+            let request = tonic::Request::new(
+                CalculationRequest::new(*first, *second, ctx).with_trace(trace),
+            );
 
             let message = clients
                 .multiplier_client
                 .multiply(request)
                 .await?
                 .into_inner();
-            Ok(message.result)
+            message.into_traced_result()
         }
         MathAST::Divide(first, second) => {
-            let request = tonic::Request::new(CalculationRequest {
-                first_arg: serde_json::to_string(&first).unwrap(),
-                second_arg: serde_json::to_string(&second).unwrap(),
-            });
+            let request = tonic::Request::new(
+                CalculationRequest::new(*first, *second, ctx).with_trace(trace),
+            );
 
             let message = clients.divider_client.divide(request).await?.into_inner();
-            Ok(message.result)
+            message.into_traced_result()
+        }
+        MathAST::Power(first, second) => {
+            let request = tonic::Request::new(
+                CalculationRequest::new(*first, *second, ctx).with_trace(trace),
+            );
+
+            let message = clients.power_client.power(request).await?.into_inner();
+            message.into_traced_result()
+        }
+        MathAST::Remainder(first, second) => {
+            let request = tonic::Request::new(
+                CalculationRequest::new(*first, *second, ctx).with_trace(trace),
+            );
+
+            let message = clients.modulo_client.remainder(request).await?.into_inner();
+            message.into_traced_result()
+        }
+        MathAST::FloorDivide(first, second) => {
+            let request = tonic::Request::new(
+                CalculationRequest::new(*first, *second, ctx).with_trace(trace),
+            );
+
+            let message = clients
+                .modulo_client
+                .floor_divide(request)
+                .await?
+                .into_inner();
+            message.into_traced_result()
+        }
+        MathAST::Call { name, args } => {
+            let request = tonic::Request::new(CallRequest::new(&name, args, ctx).with_trace(trace));
+
+            let message = clients.functions_client.call(request).await?.into_inner();
+            message.into_traced_result()
         }
     }
 }
 
+/// Apply a sign to the value of the tree under it, a Step of our own when tracing
+async fn apply_sign(
+    clients: &mut Clients,
+    ctx: &Context,
+    ast: MathAST,
+    trace: bool,
+) -> Result<(Number, Option<Step>), Status> {
+    let expr = trace.then(|| ast.clone());
+    let (op, inner) = match ast {
+        MathAST::Negate(inner) => ("-", inner),
+        MathAST::UnaryPlus(inner) => ("+", inner),
+        _ => unreachable!("only signs are applied here"),
+    };
+    let (operand, step) = run(clients, ctx, *inner, trace).await?;
+
+    let start = Instant::now();
+    let result = match op {
+        "-" => (-operand.clone()).coerce(ctx)?,
+        _ => operand.clone(),
+    };
+
+    let step = expr.map(|expr| Step {
+        expr,
+        op: op.to_string(),
+        operands: vec![operand],
+        result: result.clone(),
+        service: clients.name().to_string(),
+        elapsed: start.elapsed(),
+        steps: step.into_iter().collect(),
+    });
+    Ok((result, step))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use equation::parse::test_value;
+
+    /// Evaluate exactly what was written so the services do all of the work
+    fn as_written() -> Options {
+        Options {
+            simplify: false,
+            ..Default::default()
+        }
+    }
 
     #[actix_rt::test]
     async fn test_adder() {
         let config = Config::new();
         let mut client = build_adder_client(&config).await.unwrap();
 
-        let request = tonic::Request::new(CalculationRequest {
-            first_arg: serde_json::to_string(&MathAST::Value(1)).unwrap(),
-            second_arg: serde_json::to_string(&MathAST::Value(2)).unwrap(),
-        });
+        let request = tonic::Request::new(CalculationRequest::new(
+            MathAST::Value(1.into()),
+            MathAST::Value(2.into()),
+            &Context::default(),
+        ));
 
         let message = client.add(request).await.unwrap().into_inner();
 
-        assert_eq!(message.result, 3);
+        assert_eq!(message.into_result().unwrap(), 3.into());
+
+        // Variables are resolved by the service from the bindings sent with the request
+        let bindings: Bindings = [("x".to_string(), 4.into())].into_iter().collect();
+        let request = CalculationRequest::new(
+            MathAST::Var("x".to_string()),
+            MathAST::Multiply(
+                Box::new(MathAST::Var("x".to_string())),
+                Box::new(MathAST::Value(2.into())),
+            ),
+            &Context::default(),
+        );
+
+        let message = client
+            .add(request.clone().with_bindings(&bindings))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(message.into_result().unwrap(), 12.into());
+
+        let status = client.add(request).await.expect_err("Unbound");
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        assert_eq!(status.message(), "Unbound variable: x");
     }
 
     #[actix_rt::test]
@@ -147,14 +856,15 @@ mod tests {
         let config = Config::new();
         let mut client = build_subtractor_client(&config).await.unwrap();
 
-        let request = tonic::Request::new(CalculationRequest {
-            first_arg: serde_json::to_string(&MathAST::Value(5)).unwrap(),
-            second_arg: serde_json::to_string(&MathAST::Value(2)).unwrap(),
-        });
+        let request = tonic::Request::new(CalculationRequest::new(
+            MathAST::Value(5.into()),
+            MathAST::Value(2.into()),
+            &Context::default(),
+        ));
 
         let message = client.subtract(request).await.unwrap().into_inner();
 
-        assert_eq!(message.result, 3);
+        assert_eq!(message.into_result().unwrap(), 3.into());
     }
 
     #[actix_rt::test]
@@ -162,14 +872,15 @@ mod tests {
         let config = Config::new();
         let mut client = build_multiplier_client(&config).await.unwrap();
 
-        let request = tonic::Request::new(CalculationRequest {
-            first_arg: serde_json::to_string(&MathAST::Value(5)).unwrap(),
-            second_arg: serde_json::to_string(&MathAST::Value(2)).unwrap(),
-        });
+        let request = tonic::Request::new(CalculationRequest::new(
+            MathAST::Value(5.into()),
+            MathAST::Value(2.into()),
+            &Context::default(),
+        ));
 
         let message = client.multiply(request).await.unwrap().into_inner();
 
-        assert_eq!(message.result, 10);
+        assert_eq!(message.into_result().unwrap(), 10.into());
     }
 
     #[actix_rt::test]
@@ -177,14 +888,703 @@ mod tests {
         let config = Config::new();
         let mut client = build_divider_client(&config).await.unwrap();
 
-        let request = tonic::Request::new(CalculationRequest {
-            first_arg: serde_json::to_string(&MathAST::Value(4)).unwrap(),
-            second_arg: serde_json::to_string(&MathAST::Value(2)).unwrap(),
-        });
+        let request = tonic::Request::new(CalculationRequest::new(
+            MathAST::Value(4.into()),
+            MathAST::Value(2.into()),
+            &Context::default(),
+        ));
 
         let message = client.divide(request).await.unwrap().into_inner();
 
-        assert_eq!(message.result, 2);
+        assert_eq!(message.into_result().unwrap(), 2.into());
+    }
+
+    #[actix_rt::test]
+    async fn test_power() {
+        let config = Config::new();
+        let mut client = build_power_client(&config).await.unwrap();
+
+        let request = tonic::Request::new(CalculationRequest::new(
+            MathAST::Value(2.into()),
+            MathAST::Value(5.into()),
+            &Context::default(),
+        ));
+
+        let message = client.power(request).await.unwrap().into_inner();
+
+        assert_eq!(message.into_result().unwrap(), 32.into());
+    }
+
+    #[actix_rt::test]
+    async fn test_modulo() {
+        let config = Config::new();
+        let mut client = build_modulo_client(&config).await.unwrap();
+
+        let request = || {
+            tonic::Request::new(CalculationRequest::new(
+                MathAST::Value((-7).into()),
+                MathAST::Value(2.into()),
+                &Context::default(),
+            ))
+        };
+
+        let message = client.remainder(request()).await.unwrap().into_inner();
+        assert_eq!(message.into_result().unwrap(), (-1).into());
+
+        let message = client.floor_divide(request()).await.unwrap().into_inner();
+        assert_eq!(message.into_result().unwrap(), (-4).into());
+    }
+
+    #[actix_rt::test]
+    async fn test_functions() {
+        let config = Config::new();
+        let mut client = build_functions_client(&config).await.unwrap();
+
+        let request = tonic::Request::new(CallRequest::new(
+            "gcd",
+            vec![MathAST::Value(12.into()), MathAST::Value(18.into())],
+            &Context::default(),
+        ));
+
+        let message = client.call(request).await.unwrap().into_inner();
+
+        assert_eq!(message.into_result().unwrap(), 6.into());
+    }
+
+    #[actix_rt::test]
+    async fn test_e2e_parsed() {
+        let config = Config::new();
+        let mut clients = Clients::new(&config).await;
+        let res = evaluate(
+            &mut clients,
+            &Context::default(),
+            "(1 + 2) * (10 - 4) / 3 - 5",
+            &as_written(),
+        )
+        .await
+        .expect("Result");
+        assert_eq!(res, 1.into());
+
+        // Exponents are delegated to the power service from wherever they're found
+        let res = evaluate(
+            &mut clients,
+            &Context::default(),
+            "2 ^ 3 ^ 2 - (1 + 2) ^ 2 * 4",
+            &as_written(),
+        )
+        .await
+        .expect("Result");
+        assert_eq!(res, 476.into());
+
+        let res = evaluate(
+            &mut clients,
+            &Context::default(),
+            "-(2 * -3) + -(4 - 10) / 3",
+            &as_written(),
+        )
+        .await
+        .expect("Result");
+        assert_eq!(res, 8.into());
+
+        let res = evaluate(&mut clients, &Context::default(), "-(2 ^ 3)", &as_written())
+            .await
+            .expect("Result");
+        assert_eq!(res, (-8).into());
+
+        let ctx = Context {
+            remainder: RemainderConvention::Euclidean,
+            ..Context::default()
+        };
+        let res = evaluate(&mut clients, &ctx, "(0 - 17) % 5 + 17 // 5", &as_written())
+            .await
+            .expect("Result");
+        assert_eq!(res, 6.into());
+
+        // Arguments are evaluated by the services before the call reaches the functions service
+        let res = evaluate(
+            &mut clients,
+            &Context::default(),
+            "max(2, 1 + 2) ^ 2 + sqrt(abs(-16))",
+            &as_written(),
+        )
+        .await
+        .expect("Result");
+        assert_eq!(res, 13.into());
+
+        let ctx = Context::new(NumericDomain::Rational);
+        let res = evaluate(
+            &mut clients,
+            &ctx,
+            "2 * floor(7 / 2) - min(1 / 2, 1 / 3)",
+            &as_written(),
+        )
+        .await
+        .expect("Result");
+        assert_eq!(res.to_string(), "17/3");
+
+        // Every term of a long sum nests the tree a level deeper on the wire
+        let input = vec!["1"; 256].join(" + ");
+        let res = evaluate(&mut clients, &Context::default(), &input, &as_written())
+            .await
+            .expect("Result");
+        assert_eq!(res, 256.into());
+    }
+
+    #[actix_rt::test]
+    async fn test_e2e_bindings() {
+        let config = Config::new();
+        let mut clients = Clients::new(&config).await;
+        let formula = "rate * (hours - 40) + max(base, 100)";
+
+        // The same formula with different inputs
+        for (hours, base, expected) in [(45, 50, 150), (40, 300, 300)] {
+            let bindings: Bindings = [("rate", 10), ("hours", hours), ("base", base)]
+                .into_iter()
+                .map(|(name, v)| (name.to_string(), v.into()))
+                .collect();
+            let options = Options {
+                bindings,
+                ..Default::default()
+            };
+            let res = evaluate(&mut clients, &Context::default(), formula, &options)
+                .await
+                .expect("Result");
+            assert_eq!(res, expected.into());
+        }
+
+        let options = Options {
+            bindings: [("rate".to_string(), 10.into())].into_iter().collect(),
+            ..Default::default()
+        };
+        let status = evaluate(&mut clients, &Context::default(), formula, &options)
+            .await
+            .expect_err("Unbound");
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        assert_eq!(status.message(), "Unbound variables: base, hours");
+    }
+
+    #[test]
+    fn test_parse_binding() {
+        assert_eq!(
+            parse_binding("x=-2.5"),
+            Ok(("x".to_string(), -Number::from_literal("2.5")))
+        );
+        assert!(parse_binding("x").is_err());
+        assert!(parse_binding("2x=1").is_err());
+        assert!(parse_binding("x=1 + 1").is_err());
+    }
+
+    #[test]
+    fn test_explain_conflicts() {
+        let args = |argv: &[&str]| Args::try_parse_from([&["orchestrator"], argv].concat());
+
+        let err = args(&["x * y", "--explain", "--gradient"]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ArgumentConflict);
+        for equation in ["2 * x = 4", "{x + y = 2, x - y = 0}"] {
+            let err = args(&[equation, "--explain"])
+                .unwrap()
+                .validate()
+                .unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::ArgumentConflict);
+        }
+
+        assert!(args(&["x * y", "--explain"]).unwrap().validate().is_ok());
+        assert!(args(&["2 * x = 4"]).unwrap().validate().is_ok());
+        assert!(args(&["x y *", "--explain", "--format", "rpn"])
+            .unwrap()
+            .validate()
+            .is_ok());
+    }
+
+    #[test]
+    fn test_typeset() {
+        let ast = parse("rate * (hours - 40) / 2").unwrap();
+
+        assert_eq!(
+            typeset(Notation::Latex, ast.clone(), 25.into()),
+            "\\frac{\\mathrm{rate} \\cdot \\left(\\mathrm{hours} - 40\\right)}{2} = 25"
+        );
+        let mathml = typeset(Notation::Mathml, ast, 25.into());
+        assert!(
+            mathml.starts_with("<math xmlns=\"http://www.w3.org/1998/Math/MathML\"><mrow><mfrac>")
+        );
+        assert!(mathml.ends_with("</mfrac><mo>=</mo><mn>25</mn></mrow></math>"));
+    }
+
+    #[actix_rt::test]
+    async fn test_e2e_solve() {
+        let config = Config::new();
+        let clients = Clients::new(&config).await;
+
+        let solution = solve_equation(
+            &clients,
+            &Context::default(),
+            "( ( (3 + 3)*2) /4) – 2 = X",
+            &Bindings::new(),
+        )
+        .await
+        .expect("Solution");
+        assert_eq!(solution.unknown, "X");
+        assert_eq!(solution.value, 1.into());
+
+        let bindings: Bindings = [("rate".to_string(), 10.into())].into_iter().collect();
+        let solution = solve_equation(
+            &clients,
+            &Context::default(),
+            "rate * (hours - 40) + max(50, 100) = 250",
+            &bindings,
+        )
+        .await
+        .expect("Solution");
+        assert_eq!(solution.unknown, "hours");
+        assert_eq!(solution.value, 55.into());
+
+        let status = solve_equation(&clients, &Context::default(), "x ^ 2 = 4", &Bindings::new())
+            .await
+            .expect_err("Non-linear");
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        assert_eq!(
+            status.message(),
+            "Can't solve: x isn't linear - it appears in a power"
+        );
+
+        let status = solve_equation(&clients, &Context::default(), "x = x + 1", &Bindings::new())
+            .await
+            .expect_err("No solution");
+        assert_eq!(
+            status.message(),
+            "Can't solve: no value of x makes both sides equal"
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_solver() {
+        let config = Config::new();
+        let mut client = build_solver_client(&config).await.unwrap();
+
+        let request = SystemRequest::new(
+            parse_system("{2 * x + y = 5, x - y = 1}").unwrap(),
+            &Context::default(),
+        );
+
+        let message = client.solve_system(request).await.unwrap().into_inner();
+        let solution = message.into_solution().unwrap();
+
+        assert_eq!(
+            solution,
+            [
+                Solution {
+                    unknown: "x".to_string(),
+                    value: 2.into()
+                },
+                Solution {
+                    unknown: "y".to_string(),
+                    value: 1.into()
+                }
+            ]
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_solver_differentiate() {
+        let config = Config::new();
+        let mut client = build_solver_client(&config).await.unwrap();
+
+        let request =
+            DerivativeRequest::new(parse("x ^ 3 + y * x").unwrap(), "x", &Context::default());
+
+        let message = client.differentiate(request).await.unwrap().into_inner();
+        assert_eq!(
+            message.into_derivative().unwrap(),
+            parse("3 * x ^ 2 + y").unwrap()
+        );
+
+        let request = DerivativeRequest::new(parse("2 ^ x").unwrap(), "x", &Context::default());
+        let status = client.differentiate(request).await.expect_err("Exponent");
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        assert_eq!(
+            status.message(),
+            "Can't differentiate with respect to x: x appears in an exponent"
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_e2e_gradient() {
+        let config = Config::new();
+        let mut clients = Clients::new(&config).await;
+
+        // f(x, y) = x^2 y + sqrt(y) at (3, 4) - the gradient is (2xy, x^2 + 1 / (2 sqrt(y)))
+        let bindings: Bindings = [("x", 3), ("y", 4)]
+            .into_iter()
+            .map(|(name, v)| (name.to_string(), v.into()))
+            .collect();
+        let ctx = Context::new(NumericDomain::Rational);
+        for simplify in [true, false] {
+            let options = Options {
+                bindings: bindings.clone(),
+                simplify,
+                ..Default::default()
+            };
+            let partials = gradient(&mut clients, &ctx, "x ^ 2 * y + sqrt(y)", &options)
+                .await
+                .expect("Gradient");
+            let partials: Vec<_> = partials
+                .iter()
+                .map(|p| format!("{} {} {}", p.var, p.derivative, p.value))
+                .collect();
+            assert_eq!(
+                partials,
+                ["x 2 * x * y 24", "y x ^ 2 + 1 / (2 * sqrt(y)) 37/4"]
+            );
+        }
+
+        let status = gradient(
+            &mut clients,
+            &ctx,
+            "x % 2",
+            &Options {
+                bindings,
+                ..Default::default()
+            },
+        )
+        .await
+        .expect_err("Not differentiable");
+        assert_eq!(
+            status.message(),
+            "Can't differentiate with respect to x: % isn't differentiable"
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_e2e_solve_system() {
+        let config = Config::new();
+        let mut clients = Clients::new(&config).await;
+
+        let bindings: Bindings = [("k".to_string(), 3.into())].into_iter().collect();
+        let ctx = Context::new(NumericDomain::Rational);
+        let solution = solve_system(
+            &mut clients,
+            &ctx,
+            "{a + b = k ^ 2, a - 2 * b = sqrt(4)}",
+            &bindings,
+        )
+        .await
+        .expect("Solution");
+        let values: Vec<_> = solution.iter().map(|s| s.value.to_string()).collect();
+        assert_eq!(values, ["20/3", "7/3"]);
+
+        let status = solve_system(
+            &mut clients,
+            &ctx,
+            "{x + y = 1, x + y = 2}",
+            &Bindings::new(),
+        )
+        .await
+        .expect_err("Inconsistent");
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        assert_eq!(
+            status.message(),
+            "Can't solve: the equations contradict each other"
+        );
+
+        let status = solve_system(
+            &mut clients,
+            &ctx,
+            "{x + y = 1, 3 * x + 3 * y = 3}",
+            &Bindings::new(),
+        )
+        .await
+        .expect_err("Underdetermined");
+        assert_eq!(
+            status.message(),
+            "Can't solve: the equations don't determine y - there are infinitely many solutions"
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_e2e_simplify() {
+        let config = Config::new();
+        let mut clients = Clients::new(&config).await;
+
+        // Same results with and without simplifying first
+        for (input, domain) in [
+            ("(1 + 2) * (10 - 4) / 3 - 5 + x * 1", NumericDomain::Int32),
+            ("-(-(x)) * 2 - x + max(x, 2 * 0)", NumericDomain::Int64),
+            ("(7 / 2 - x) * (x + 0) / 1", NumericDomain::Rational),
+        ] {
+            let ctx = Context::new(domain);
+            let bindings: Bindings = [("x".to_string(), 3.into())].into_iter().collect();
+            let simplified = Options {
+                bindings: bindings.clone(),
+                ..Default::default()
+            };
+            let written = Options {
+                bindings,
+                ..as_written()
+            };
+
+            let res = evaluate(&mut clients, &ctx, input, &simplified)
+                .await
+                .expect("Result");
+            let expected = evaluate(&mut clients, &ctx, input, &written)
+                .await
+                .expect("Result");
+            assert_eq!(res, expected, "{}", input);
+        }
+
+        // Errors that stop folding are still reported by the services
+        let status = evaluate(
+            &mut clients,
+            &Context::default(),
+            "1 + 4 / (2 - 2)",
+            &Options::default(),
+        )
+        .await
+        .expect_err("Division by zero");
+        assert_eq!(status.message(), "Division by zero");
+    }
+
+    #[actix_rt::test]
+    async fn test_e2e_parse_error() {
+        let config = Config::new();
+        let mut clients = Clients::new(&config).await;
+        let status = evaluate(
+            &mut clients,
+            &Context::default(),
+            "(1 + 2) * ",
+            &Options::default(),
+        )
+        .await
+        .expect_err("Parse error");
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        assert!(status.message().contains("found end of input"));
+    }
+
+    #[actix_rt::test]
+    async fn test_e2e_trace() {
+        let config = Config::new();
+        let mut clients = Clients::new(&config).await;
+        let ctx = Context::default();
+        let summary = |step: &Step| (step.op.clone(), step.service.clone(), step.result.clone());
+
+        let (res, step) = trace(&mut clients, &ctx, EXERCISE, &as_written())
+            .await
+            .expect("Result");
+        let step = step.expect("Steps");
+        assert_eq!(res, 1.into());
+        assert_eq!(step.expr, test_value());
+        assert_eq!(
+            step.flatten().into_iter().map(summary).collect::<Vec<_>>(),
+            vec![
+                ("-".to_string(), "Subtractor".to_string(), 1.into()),
+                ("/".to_string(), "Divider".to_string(), 3.into()),
+                ("*".to_string(), "Multiplier".to_string(), 12.into()),
+                ("+".to_string(), "Adder".to_string(), 6.into()),
+            ]
+        );
+
+        // The orchestrator's own signs join the trees the services send back
+        let input = "-(2 * 3 + max(1, 4))";
+        let (res, step) = trace(&mut clients, &ctx, input, &as_written())
+            .await
+            .expect("Result");
+        let step = step.expect("Steps");
+        assert_eq!(res, (-10).into());
+        assert_eq!(
+            step.flatten().into_iter().map(summary).collect::<Vec<_>>(),
+            vec![
+                ("-".to_string(), "Orchestrator".to_string(), (-10).into()),
+                ("+".to_string(), "Adder".to_string(), 10.into()),
+                ("*".to_string(), "Multiplier".to_string(), 6.into()),
+                ("max".to_string(), "Functions".to_string(), 4.into()),
+            ]
+        );
+        assert_eq!(step.steps[0].operands, vec![6.into(), 4.into()]);
+
+        // Simplifying by default folds the whole exercise before anything is sent
+        let (res, step) = trace(&mut clients, &ctx, EXERCISE, &Options::default())
+            .await
+            .expect("Result");
+        assert_eq!((res, step), (1.into(), None));
+    }
+
+    #[actix_rt::test]
+    async fn test_e2e_formats() {
+        let config = Config::new();
+        let mut clients = Clients::new(&config).await;
+
+        for (format, input) in [
+            (Format::Infix, "( ( (3 + 3)*2) /4) – x"),
+            (Format::Rpn, "3 3 + 2 * 4 / x -"),
+            (Format::Sexpr, "(- (/ (* (+ 3 3) 2) 4) x)"),
+        ] {
+            let options = Options {
+                bindings: [("x".to_string(), 2.into())].into_iter().collect(),
+                format,
+                ..Default::default()
+            };
+            let res = evaluate(&mut clients, &Context::default(), input, &options)
+                .await
+                .expect("Result");
+            assert_eq!(res, 1.into(), "{:?}", format);
+        }
+
+        // Errors carry the same caret diagnostic whatever the syntax
+        let options = Options {
+            format: Format::Rpn,
+            ..Default::default()
+        };
+        let status = evaluate(&mut clients, &Context::default(), "1 2 + *", &options)
+            .await
+            .expect_err("Parse error");
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        assert!(status
+            .message()
+            .contains("expected 2 operands for '*', found 1 operand"));
+        assert!(status.message().contains("      ^"));
+    }
+
+    #[actix_rt::test]
+    async fn test_e2e_division_by_zero() {
+        let config = Config::new();
+        let mut clients = Clients::new(&config).await;
+
+        // Entered at the adder - the divider's error has to make it back through the delegation
+        let status = evaluate(
+            &mut clients,
+            &Context::default(),
+            "1 + 4 / (2 - 2)",
+            &Options::default(),
+        )
+        .await
+        .expect_err("Division by zero");
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        assert_eq!(status.message(), "Division by zero");
+
+        let status = evaluate(
+            &mut clients,
+            &Context::default(),
+            "2 * (5 % (3 - 3))",
+            &Options::default(),
+        )
+        .await
+        .expect_err("Division by zero");
+        assert_eq!(status.message(), "Division by zero");
+
+        let status = evaluate(
+            &mut clients,
+            &Context::default(),
+            "1 + max(1, 2 / 0)",
+            &Options::default(),
+        )
+        .await
+        .expect_err("Division by zero");
+        assert_eq!(status.message(), "Division by zero");
+    }
+
+    #[actix_rt::test]
+    async fn test_e2e_overflow() {
+        let config = Config::new();
+        let mut clients = Clients::new(&config).await;
+
+        let status = evaluate(
+            &mut clients,
+            &Context::default(),
+            "(2147483647 + 1) - 1",
+            &Options::default(),
+        )
+        .await
+        .expect_err("Overflow");
+        assert_eq!(status.code(), tonic::Code::OutOfRange);
+        assert_eq!(status.message(), "Overflow evaluating 2147483647 + 1");
+    }
+
+    #[actix_rt::test]
+    async fn test_e2e_domains() {
+        let config = Config::new();
+        let mut clients = Clients::new(&config).await;
+
+        let ctx = Context::new(NumericDomain::Int64);
+        let res = evaluate(
+            &mut clients,
+            &ctx,
+            "(2147483647 + 1) * 2 - 1",
+            &Options::default(),
+        )
+        .await
+        .expect("Result");
+        assert_eq!(res, Number::I64(4294967295));
+
+        let ctx = Context::new(NumericDomain::BigInteger);
+        let res = evaluate(
+            &mut clients,
+            &ctx,
+            "(9223372036854775807 * 9223372036854775807) / 3 + 1",
+            &Options::default(),
+        )
+        .await
+        .expect("Result");
+        assert_eq!(res.to_string(), "28356863910078205282465635928077500417");
+
+        let ctx = Context::new(NumericDomain::Rational);
+        let res = evaluate(
+            &mut clients,
+            &ctx,
+            "(7 / 2) - (1 / 3 + 1 / 6) * 4",
+            &Options::default(),
+        )
+        .await
+        .expect("Result");
+        assert_eq!(res.to_string(), "3/2");
+        assert_eq!(res.to_decimal_string(2), "1.50");
+
+        // Literals too wide for the requested domain are rejected, not truncated
+        let status = evaluate(
+            &mut clients,
+            &Context::default(),
+            "4294967296 + 1",
+            &Options::default(),
+        )
+        .await
+        .expect_err("Out of domain");
+        assert_eq!(status.code(), tonic::Code::OutOfRange);
+    }
+
+    #[actix_rt::test]
+    async fn test_e2e_float_and_decimal() {
+        let config = Config::new();
+        let mut clients = Clients::new(&config).await;
+
+        let ctx = Context::new(NumericDomain::Float64);
+        let res = evaluate(&mut clients, &ctx, "0.5 * 3 + 1 / 0", &Options::default())
+            .await
+            .expect("Result");
+        assert_eq!(res, Number::F64(f64::INFINITY));
+        assert_eq!(res.outcomes(), vec![Outcome::Infinite]);
+
+        let ctx = Context::decimal(2, Rounding::HalfEven);
+        let res = evaluate(
+            &mut clients,
+            &ctx,
+            "(10.00 / 3) * 3 - 0.005",
+            &Options::default(),
+        )
+        .await
+        .expect("Result");
+        assert_eq!(res.to_string(), "9.99");
+        assert_eq!(res.outcomes(), vec![Outcome::Rounded]);
+
+        let ctx = Context::decimal(2, Rounding::HalfUp);
+        let res = evaluate(&mut clients, &ctx, "1.005 + 1", &Options::default())
+            .await
+            .expect("Result");
+        assert_eq!(res.to_string(), "2.01");
+
+        // Division by zero is still an error outside IEEE floats
+        let status = evaluate(&mut clients, &ctx, "1 / (0.5 - 0.5)", &Options::default())
+            .await
+            .expect_err("Division by zero");
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
     }
 
     #[actix_rt::test]
@@ -192,7 +1592,9 @@ mod tests {
         let config = Config::new();
         let mut clients = Clients::new(&config).await;
         let ast = test_value();
-        let res = run_equation(&mut clients, ast).await.expect("Result");
-        assert_eq!(res, 1);
+        let res = run_equation(&mut clients, &Context::default(), ast)
+            .await
+            .expect("Result");
+        assert_eq!(res, 1.into());
     }
 }
@@ -0,0 +1,5 @@
+use equation::service::{serve, Solver};
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    serve::<Solver>()
+}